@@ -1,141 +1,562 @@
 // SPDX-License-Identifier: MIT
 
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Duration, Utc};
+use reqwest::blocking::{Client, RequestBuilder, Response};
 use reqwest::header::{self, HeaderMap, HeaderValue};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use zeroize::Zeroize;
 
 use crate::api_definition::{
-    PreloginRequest, PreloginResponse, LoginRequest, LoginResponse, SyncResponse
+    CipherEntry, CreateSharedCipherRequest, EmailCodeRequest, ErrorResponse, PreloginRequest,
+    LoginRequest, LoginResponse, RefreshRequest, SyncResponse, TwoFactorChallenge,
+    TwoFactorProvider,
+};
+use crate::cipher::{
+    CipherError, CipherString, CipherSuite, Decrypt, Kdf, KdfParams, SecretString,
 };
-use crate::cipher::CipherSuite;
 
-const AUTH_URL: &str = "https://identity.bitwarden.com/connect/token";
-const BASE_URL: &str = "https://api.bitwarden.com";
+const DEFAULT_IDENTITY_URL: &str = "https://identity.bitwarden.com";
+const DEFAULT_API_URL: &str = "https://api.bitwarden.com";
+const CLIENT_ID: &str = "connector";
+
+// Purpose of the key the stored tokens are encrypted with
+const TOKEN_KEY: &str = "bwtui-auth-tokens";
 
-#[derive(Clone, Debug, failure::Fail)]
+// Refresh access tokens a bit before they actually expire, so they do not run out mid-request
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+#[derive(Debug, thiserror::Error)]
 pub enum ApiError {
-    #[fail(display = "network error: failed to retrieve {}", 0)]
-    NetworkError(String),
+    /// The server could not be reached at all.
+    #[error("network error: {0}")]
+    Network(#[source] reqwest::Error),
+
+    /// The server answered with an unexpected status code.
+    #[error("server rejected request to {url}: {status}")]
+    Status { url: String, status: StatusCode },
 
-    #[fail(display = "authentication failed: wrong username or password")]
+    /// The server answered with something other than expected.
+    #[error("received invalid response from {url}: {source}")]
+    Parse {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error(transparent)]
+    Crypto(#[from] CipherError),
+
+    #[error("authentication failed: wrong username or password")]
     LoginFailed,
 
-    #[fail(display = "failed to sync vault: {}", 0)]
-    SyncFailed(String),
+    #[error("session expired: please log in again")]
+    SessionExpired,
+
+    #[error("two-factor authentication required")]
+    TwoFactorRequired(Vec<TwoFactorProvider>),
+
+    /// The server refused a change, like an invalid item.
+    #[error("server rejected the change: {0}")]
+    Rejected(String),
+
+    #[error("item was changed on the server in the meantime, sync and try again")]
+    OutOfDate,
 }
 
 pub type ApiResult<T> = Result<T, ApiError>;
 
+/// Endpoints of the bitwarden server to talk to.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ServerConfig {
+    pub identity_url: String,
+    pub api_url: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            identity_url: DEFAULT_IDENTITY_URL.to_owned(),
+            api_url: DEFAULT_API_URL.to_owned(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Derives both endpoints from the base URL of a self-hosted installation, which serves
+    /// the identity service at `<base>/identity` and the API at `<base>/api`.
+    pub fn from_base_url(base_url: &str) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+
+        Self {
+            identity_url: format!("{}/identity", base_url),
+            api_url: format!("{}/api", base_url),
+        }
+    }
+
+    /// Returns the base URL this configuration was derived from, if it follows the
+    /// self-hosted layout.
+    pub fn base_url(&self) -> Option<&str> {
+        let base_url = self.api_url.strip_suffix("/api")?;
+
+        if self.identity_url.strip_suffix("/identity") == Some(base_url) {
+            Some(base_url)
+        } else {
+            None
+        }
+    }
+
+    fn identity(&self, path: &str) -> String {
+        format!("{}{}", self.identity_url.trim_end_matches('/'), path)
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!("{}{}", self.api_url.trim_end_matches('/'), path)
+    }
+}
+
+/// Identifies this client to the server. Remembered two-factor logins are only accepted from the
+/// device they were issued to, so it should be persisted between logins.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Device {
+    pub identifier: Uuid,
+    #[serde(default)]
+    pub remember_token: Option<String>,
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Self {
+            identifier: Uuid::new_v4(),
+            remember_token: None,
+        }
+    }
+}
+
+/// Second factor to send along with the master password.
+#[derive(Clone, Debug)]
+pub struct TwoFactorToken {
+    pub provider: TwoFactorProvider,
+    pub token: String,
+    /// Ask the server for a token to skip two-factor authentication on this device next time.
+    pub remember: bool,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AuthData {
+    #[serde(default)]
+    pub server: ServerConfig,
+
+    // Stored by older versions in plaintext, nowadays only stored encrypted
+    #[serde(default, skip_serializing)]
     access_token: String,
     expires_in: usize,
     token_type: String,
+    #[serde(default, skip_serializing)]
+    refresh_token: Option<String>,
+    /// Access and refresh token as stored, encrypted with a key derived from the user key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encrypted_tokens: Option<CipherString>,
+    #[serde(default)]
+    issued_at: Option<DateTime<Utc>>,
 
-    kdf: u32,
-    pub kdf_iterations: u32,
+    #[serde(flatten)]
+    pub kdf: Kdf,
 
     #[serde(skip)]
     pub cipher: CipherSuite,
 }
 
-fn perform_prelogin(
-    client: &reqwest::blocking::Client,
-    email: &str,
-) -> ApiResult<PreloginResponse> {
-    let url = format!("{}/accounts/prelogin", BASE_URL);
+impl AuthData {
+    /// Whether the access token has (nearly) expired and must be refreshed before use.
+    pub fn is_expired(&self) -> bool {
+        match self.issued_at {
+            Some(issued_at) => {
+                let lifetime = Duration::seconds(self.expires_in as i64 - EXPIRY_MARGIN_SECS);
+                Utc::now() >= issued_at + lifetime
+            }
+            None => true,
+        }
+    }
+
+    /// Encrypts the tokens for storing them, which requires the user key.
+    pub fn seal(&mut self) -> Result<(), CipherError> {
+        let key = self.cipher.key_for(None).ok_or_else(|| {
+            CipherError::DecryptionKeyError("user key not available".to_owned())
+        })?;
+
+        let tokens = SecretString::from(format!(
+            "{}\n{}",
+            self.access_token,
+            self.refresh_token.as_deref().unwrap_or_default(),
+        ));
+
+        let key = key.derive(TOKEN_KEY);
+        self.encrypted_tokens = Some(CipherString::encrypt(tokens.as_bytes(), &key));
+
+        Ok(())
+    }
+
+    /// Decrypts the stored tokens once the user key is available. Tokens which are already
+    /// available, like after logging in or from plaintext data of older versions, are kept.
+    pub fn unseal(&mut self) -> Result<(), CipherError> {
+        if !self.access_token.is_empty() {
+            return Ok(());
+        }
+
+        let (encrypted, key) = match (&self.encrypted_tokens, self.cipher.key_for(None)) {
+            (Some(encrypted), Some(key)) => (encrypted, key.derive(TOKEN_KEY)),
+            (None, _) => return Ok(()),
+            (_, None) => {
+                return Err(CipherError::DecryptionKeyError("user key not available".to_owned()))
+            }
+        };
+
+        let tokens = encrypted.decrypt(&key).ok_or(CipherError::InvalidMac)?;
+        let (access_token, refresh_token) = tokens.split_once('\n').unwrap_or((&tokens, ""));
+
+        self.access_token = access_token.to_owned();
+        self.refresh_token = Some(refresh_token.to_owned()).filter(|token| !token.is_empty());
+
+        Ok(())
+    }
+
+    /// Whether there is a token to authorize requests with, i.e. the session has not been ended
+    /// by the server rejecting its tokens. Sealed tokens only count once unsealed.
+    pub fn has_session(&self) -> bool {
+        !self.access_token.is_empty() || self.refresh_token.is_some()
+    }
+
+    /// Forgets the tokens once the server rejected them, so that logging in again starts a new
+    /// session instead of reusing them.
+    fn end_session(&mut self) {
+        self.access_token.zeroize();
+        self.refresh_token.zeroize();
+        self.encrypted_tokens = None;
+        self.issued_at = None;
+    }
+
+    /// Drops the plaintext tokens along with the keys. Only the sealed tokens are kept, which
+    /// `unseal` decrypts again after unlocking.
+    pub fn lock(&mut self) {
+        self.access_token.zeroize();
+        self.refresh_token.zeroize();
+        self.cipher = CipherSuite::default();
+    }
+
+    fn update_token(&mut self, response: LoginResponse) {
+        self.access_token = response.access_token;
+        self.expires_in = response.expires_in;
+        self.token_type = response.token_type;
+        self.issued_at = Some(Utc::now());
+
+        if response.refresh_token.is_some() {
+            self.refresh_token = response.refresh_token;
+        }
+    }
+}
+
+fn send(request: RequestBuilder) -> ApiResult<Response> {
+    request.send().map_err(ApiError::Network)
+}
+
+fn parse<T: DeserializeOwned>(response: Response) -> ApiResult<T> {
+    let url = response.url().to_string();
+    response.json().map_err(|source| ApiError::Parse { url, source })
+}
+
+fn status_error(response: &Response) -> ApiError {
+    ApiError::Status {
+        url: response.url().to_string(),
+        status: response.status(),
+    }
+}
+
+/// Reports the server's message for rejected changes.
+fn change_error(auth_data: &mut AuthData, response: Response) -> ApiError {
+    match response.status() {
+        StatusCode::BAD_REQUEST => {
+            let error = status_error(&response);
+            response.json::<ErrorResponse>().map_or(error, |body| ApiError::Rejected(body.message))
+        }
+        StatusCode::UNAUTHORIZED => {
+            auth_data.end_session();
+            ApiError::SessionExpired
+        }
+        _ => status_error(&response),
+    }
+}
+
+fn perform_prelogin(client: &Client, server: &ServerConfig, email: &str) -> ApiResult<Kdf> {
+    let url = server.api("/accounts/prelogin");
 
     let data = PreloginRequest { email };
-    let response = client
-        .post(&url)
-        .json(&data)
-        .send()
-        .map_err(|err| ApiError::NetworkError(err.to_string()))?;
+    let response = send(client.post(&url).json(&data))?;
 
     if response.status().is_success() {
-        response.json::<PreloginResponse>().map_err(|_| ApiError::LoginFailed)
+        Ok(Kdf::try_from(parse::<KdfParams>(response)?)?)
     } else {
-        Err(ApiError::LoginFailed)
+        Err(status_error(&response))
     }
 }
 
 fn perform_token_auth(
-    client: &reqwest::blocking::Client,
+    client: &Client,
+    server: &ServerConfig,
+    device: &Device,
     email: &str,
     cipher: &CipherSuite,
+    two_factor: Option<&TwoFactorToken>,
 ) -> ApiResult<LoginResponse> {
-    let device_id = Uuid::new_v4().to_hyphenated().to_string();
+    let device_id = device.identifier.to_hyphenated().to_string();
 
     let data = LoginRequest {
         grant_type: "password",
         username: email,
         scope: "api offline_access",
-        client_id: "connector",
+        client_id: CLIENT_ID,
         device_type: 3,
         device_id: &device_id,
         device_name: "bwtui",
         password: &cipher.master_key_hash,
+        two_factor_token: two_factor.map(|tf| tf.token.as_str()),
+        two_factor_provider: two_factor.map(|tf| tf.provider as u8),
+        two_factor_remember: two_factor.map(|tf| tf.remember as u8),
     };
 
-    let response = client
-        .post(AUTH_URL)
-        .form(&data)
-        .send()
-        .map_err(|err| ApiError::NetworkError(err.to_string()))?;
+    let url = server.identity("/connect/token");
+    let response = send(client.post(&url).form(&data))?;
 
     if response.status().is_success() {
-        response.json::<LoginResponse>().map_err(|_| ApiError::LoginFailed)
-    } else {
-        Err(ApiError::LoginFailed)
+        return parse(response);
+    }
+
+    // Wrong credentials and missing second factors are both rejected as bad requests
+    if response.status() != StatusCode::BAD_REQUEST {
+        return Err(status_error(&response));
+    }
+
+    match response.json::<TwoFactorChallenge>() {
+        Ok(challenge) => {
+            let mut providers: Vec<_> = challenge
+                .two_factor_providers2
+                .keys()
+                .filter_map(|id| id.parse().ok().and_then(TwoFactorProvider::from_id))
+                .collect();
+
+            providers.sort_by_key(|provider| *provider as u8);
+            Err(ApiError::TwoFactorRequired(providers))
+        }
+        Err(_) => Err(ApiError::LoginFailed),
     }
 }
 
-pub fn authenticate(email: &str, password: &str) -> ApiResult<AuthData> {
-    let client = reqwest::blocking::Client::new();
+/// Asks the server to send a two-factor login code to the account's email address.
+pub fn send_email_code(server: &ServerConfig, email: &str, password: &str) -> ApiResult<()> {
+    let client = Client::new();
 
-    let PreloginResponse {
-        kdf,
-        kdf_iterations,
-    } = perform_prelogin(&client, email)?;
+    let kdf = perform_prelogin(&client, server, email)?;
+    let cipher = CipherSuite::from(email, password, kdf)?;
+
+    let url = server.api("/two-factor/send-email-login");
+    let data = EmailCodeRequest {
+        email,
+        master_password_hash: &cipher.master_key_hash,
+    };
+
+    let response = send(client.post(&url).json(&data))?;
+
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        StatusCode::BAD_REQUEST => Err(ApiError::LoginFailed),
+        _ => Err(status_error(&response)),
+    }
+}
 
-    let cipher = CipherSuite::from(email, password, kdf_iterations);
+/// Logs in using the master password and, if required, a second factor. Without an explicit
+/// second factor, the remembered token of the device is tried. The device's remember token is
+/// updated according to the server's answer.
+pub fn authenticate(
+    server: &ServerConfig,
+    device: &mut Device,
+    email: &str,
+    password: &str,
+    two_factor: Option<&TwoFactorToken>,
+) -> ApiResult<AuthData> {
+    let client = Client::new();
+
+    let kdf = perform_prelogin(&client, server, email)?;
+    let cipher = CipherSuite::from(email, password, kdf)?;
+
+    let remembered = device.remember_token.clone().map(|token| TwoFactorToken {
+        provider: TwoFactorProvider::Remember,
+        token,
+        remember: false,
+    });
+
+    let response = perform_token_auth(
+        &client,
+        server,
+        device,
+        email,
+        &cipher,
+        two_factor.or(remembered.as_ref()),
+    );
+
+    if two_factor.is_none() && matches!(response, Err(ApiError::TwoFactorRequired(_))) {
+        // The remembered token (if any) was not accepted
+        device.remember_token = None;
+    }
 
     let LoginResponse {
         access_token,
         expires_in,
         token_type,
-    } = perform_token_auth(&client, email, &cipher)?;
+        refresh_token,
+        two_factor_token,
+    } = response?;
+
+    if two_factor_token.is_some() {
+        device.remember_token = two_factor_token;
+    }
 
     Ok(AuthData {
+        server: server.clone(),
         access_token,
         expires_in,
         token_type,
+        refresh_token,
+        encrypted_tokens: None,
+        issued_at: Some(Utc::now()),
         kdf,
-        kdf_iterations,
         cipher,
     })
 }
 
-pub fn sync(auth_data: &AuthData) -> ApiResult<SyncResponse> {
-    let url = format!("{}/sync", BASE_URL);
+/// Exchanges the refresh token for a new access token, without requiring the master password.
+pub fn refresh(auth_data: &mut AuthData) -> ApiResult<()> {
+    let refresh_token = auth_data.refresh_token.as_ref().ok_or(ApiError::SessionExpired)?;
+    let url = auth_data.server.identity("/connect/token");
 
-    let mut headers = HeaderMap::new();
+    let data = RefreshRequest {
+        grant_type: "refresh_token",
+        client_id: CLIENT_ID,
+        refresh_token,
+    };
+
+    let response = send(Client::new().post(&url).form(&data))?;
+
+    match response.status() {
+        status if status.is_success() => {
+            auth_data.update_token(parse(response)?);
+            Ok(())
+        }
+        // The refresh token has expired or was revoked
+        StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED => {
+            auth_data.end_session();
+            Err(ApiError::SessionExpired)
+        }
+        _ => Err(status_error(&response)),
+    }
+}
+
+/// Builds the headers of authorized API requests, refreshing the access token first if it has
+/// expired.
+fn auth_headers(auth_data: &mut AuthData) -> ApiResult<HeaderMap> {
+    if auth_data.is_expired() {
+        refresh(auth_data)?;
+    }
+
+    // A token which does not even fit into a header is of no use either
     let auth_header = format!("{} {}", auth_data.token_type, auth_data.access_token);
-    headers.insert(
-        header::AUTHORIZATION,
-        HeaderValue::from_str(&auth_header).unwrap(),
-    );
+    let auth_header = HeaderValue::from_str(&auth_header).map_err(|_| ApiError::SessionExpired)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::AUTHORIZATION, auth_header);
 
-    let response = reqwest::blocking::Client::new()
-        .get(&url)
-        .headers(headers)
-        .send()
-        .map_err(|_| ApiError::NetworkError(url))?;
+    Ok(headers)
+}
 
-    if response.status().is_success() {
-        response.json::<SyncResponse>()
-            .map_err(|_| ApiError::SyncFailed("received invalid response".to_string()))
-    } else {
-        Err(ApiError::SyncFailed("server reject the request".to_string()))
+/// Retrieves the vault, refreshing the access token first if it has expired.
+pub fn sync(auth_data: &mut AuthData) -> ApiResult<SyncResponse> {
+    let headers = auth_headers(auth_data)?;
+    let url = auth_data.server.api("/sync");
+
+    let response = send(Client::new().get(&url).headers(headers))?;
+
+    match response.status() {
+        status if status.is_success() => parse(response),
+        StatusCode::UNAUTHORIZED => {
+            auth_data.end_session();
+            Err(ApiError::SessionExpired)
+        }
+        _ => Err(status_error(&response)),
+    }
+}
+
+/// Creates a new item, in the collections given by `entry.collection_ids` if it belongs to an
+/// organization. Returns the item as stored by the server.
+pub fn create_cipher(auth_data: &mut AuthData, entry: &CipherEntry) -> ApiResult<CipherEntry> {
+    let headers = auth_headers(auth_data)?;
+    let client = Client::new();
+
+    let request = match entry.organization_id {
+        Some(_) => {
+            let data = CreateSharedCipherRequest {
+                cipher: entry.to_request(false),
+                collection_ids: &entry.collection_ids,
+            };
+
+            client.post(&auth_data.server.api("/ciphers/create")).json(&data)
+        }
+        None => client.post(&auth_data.server.api("/ciphers")).json(&entry.to_request(false)),
+    };
+
+    let response = send(request.headers(headers))?;
+
+    if !response.status().is_success() {
+        return Err(change_error(auth_data, response));
+    }
+
+    let mut created: CipherEntry = parse(response)?;
+    created.collection_ids = entry.collection_ids.clone();
+
+    Ok(created)
+}
+
+/// Saves the changes to an existing item. Changes made on the server since the item was synced
+/// (as told by `entry.last_changed`) are not overwritten, `ApiError::OutOfDate` is returned
+/// instead. Returns the item as stored by the server.
+pub fn update_cipher(auth_data: &mut AuthData, entry: &CipherEntry) -> ApiResult<CipherEntry> {
+    let headers = auth_headers(auth_data)?;
+    let client = Client::new();
+    let url = auth_data.server.api(&format!("/ciphers/{}", entry.uuid));
+
+    let response = send(client.get(&url).headers(headers.clone()))?;
+
+    if !response.status().is_success() {
+        return Err(change_error(auth_data, response));
+    }
+
+    // Not all servers check the revision date sent along with the changes
+    let current: CipherEntry = parse(response)?;
+    if current.last_changed > entry.last_changed {
+        return Err(ApiError::OutOfDate);
+    }
+
+    let response = send(client.put(&url).headers(headers).json(&entry.to_request(true)))?;
+
+    if !response.status().is_success() {
+        return Err(change_error(auth_data, response));
     }
+
+    // Only the sync tells the collections of an item
+    let mut updated: CipherEntry = parse(response)?;
+    updated.collection_ids = entry.collection_ids.clone();
+
+    Ok(updated)
 }
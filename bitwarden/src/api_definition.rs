@@ -1,37 +1,50 @@
 // SPDX-License-Identifier: MIT
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
 use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
 
-use crate::cipher::CipherString;
+use crate::cipher::{
+    CipherError, CipherString, CipherSuite, Decrypt, SecretString, SymmetricKey,
+};
 
 #[derive(Debug, Serialize)]
 pub(crate) struct PreloginRequest<'a> {
     pub email: &'a str,
 }
 
-#[derive(Debug, Deserialize)]
-pub(crate) struct PreloginResponse {
-    #[serde(alias = "Kdf")]
-    pub kdf: u32,
-    #[serde(alias = "KdfIterations")]
-    pub kdf_iterations: u32,
-}
-
 #[derive(Debug, Serialize)]
 pub(crate) struct LoginRequest<'a> {
     pub grant_type: &'a str,
     pub username: &'a str,
     pub scope: &'a str,
     pub client_id: &'a str,
-    #[serde(alias = "deviceType")]
+    #[serde(rename = "deviceType")]
     pub device_type: usize,
-    #[serde(alias = "deviceIdentifier")]
+    #[serde(rename = "deviceIdentifier")]
     pub device_id: &'a str,
-    #[serde(alias = "deviceName")]
+    #[serde(rename = "deviceName")]
     pub device_name: &'a str,
     pub password: &'a str,
+    #[serde(rename = "twoFactorToken", skip_serializing_if = "Option::is_none")]
+    pub two_factor_token: Option<&'a str>,
+    #[serde(rename = "twoFactorProvider", skip_serializing_if = "Option::is_none")]
+    pub two_factor_provider: Option<u8>,
+    #[serde(rename = "twoFactorRemember", skip_serializing_if = "Option::is_none")]
+    pub two_factor_remember: Option<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct RefreshRequest<'a> {
+    pub grant_type: &'a str,
+    pub client_id: &'a str,
+    pub refresh_token: &'a str,
 }
 
 #[derive(Debug, Deserialize)]
@@ -39,76 +52,447 @@ pub(crate) struct LoginResponse {
     pub access_token: String,
     pub expires_in: usize,
     pub token_type: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default, alias = "TwoFactorToken", alias = "twoFactorToken")]
+    pub two_factor_token: Option<String>,
+}
+
+/// Error body returned by the identity endpoint if a second factor is required.
+#[derive(Debug, Deserialize)]
+pub(crate) struct TwoFactorChallenge {
+    #[serde(alias = "TwoFactorProviders2", alias = "twoFactorProviders2")]
+    pub two_factor_providers2: HashMap<String, IgnoredAny>,
+}
+
+/// Body of requests rejected by the API, like invalid changes to an item.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ErrorResponse {
+    #[serde(alias = "Message")]
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct EmailCodeRequest<'a> {
+    pub email: &'a str,
+    #[serde(rename = "masterPasswordHash")]
+    pub master_password_hash: &'a str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TwoFactorProvider {
+    Authenticator = 0,
+    Email = 1,
+    Duo = 2,
+    YubiKey = 3,
+    U2f = 4,
+    Remember = 5,
+    OrganizationDuo = 6,
+    WebAuthn = 7,
+}
+
+impl TwoFactorProvider {
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::Authenticator),
+            1 => Some(Self::Email),
+            2 => Some(Self::Duo),
+            3 => Some(Self::YubiKey),
+            4 => Some(Self::U2f),
+            5 => Some(Self::Remember),
+            6 => Some(Self::OrganizationDuo),
+            7 => Some(Self::WebAuthn),
+            _ => None,
+        }
+    }
+
+    /// Whether the provider works by entering a code, which is all a terminal can offer.
+    pub fn is_supported(self) -> bool {
+        matches!(self, Self::Authenticator | Self::Email | Self::YubiKey)
+    }
+}
+
+impl fmt::Display for TwoFactorProvider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Authenticator => "authenticator app",
+            Self::Email => "email",
+            Self::Duo => "Duo",
+            Self::YubiKey => "YubiKey OTP",
+            Self::U2f => "FIDO U2F",
+            Self::Remember => "remembered device",
+            Self::OrganizationDuo => "Duo (organization)",
+            Self::WebAuthn => "FIDO2 WebAuthn",
+        };
+
+        f.write_str(name)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Profile {
     #[serde(alias = "Object")]
     object: String,
-    #[serde(alias = "Id")]
+    #[serde(alias = "Id", alias = "id")]
     pub uuid: Uuid,
     #[serde(alias = "Name")]
-    pub name: String,
+    pub name: Option<String>,
     #[serde(alias = "Email")]
     pub email: String,
-    #[serde(alias = "EmailVerified")]
+    #[serde(alias = "EmailVerified", alias = "emailVerified")]
     pub email_verified: bool,
     #[serde(alias = "Premium")]
     pub premium: bool,
-    #[serde(alias = "MasterPasswordHint")]
+    #[serde(alias = "MasterPasswordHint", alias = "masterPasswordHint")]
     pub master_password_hint: Option<String>,
-    #[serde(alias = "Culture")]
+    #[serde(alias = "Culture", alias = "culture")]
     pub language: String,
-    #[serde(alias = "TwoFactorEnabled")]
+    #[serde(alias = "TwoFactorEnabled", alias = "twoFactorEnabled")]
     pub tfa_enabled: bool,
     #[serde(alias = "Key")]
     pub key: CipherString,
-    #[serde(alias = "PrivateKey")]
+    #[serde(alias = "PrivateKey", alias = "privateKey")]
     pub private_key: CipherString,
-    #[serde(alias = "SecurityStamp")]
+    #[serde(alias = "SecurityStamp", alias = "securityStamp")]
     pub security_stamp: String,
     #[serde(alias = "Organizations")]
-    pub organizations: Vec<String>,
+    pub organizations: Vec<ProfileOrganization>,
+}
+
+impl Profile {
+    /// Unlocks the user's private key and with it the keys of all organizations the user is a
+    /// confirmed member of. Requires the user key to be set. Returns the organizations whose key
+    /// could not be unwrapped, the others are unlocked nevertheless.
+    pub fn unlock_organizations(&self, cipher: &mut CipherSuite) -> Result<Vec<Uuid>, CipherError> {
+        cipher.set_private_key(&self.private_key)?;
+
+        // Invited but not yet confirmed members have no access to the organization key
+        cipher.set_organization_keys(
+            self.organizations
+                .iter()
+                .filter_map(|org| Some((org.uuid, org.key.as_ref()?))),
+        )
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ProfileOrganization {
+    #[serde(alias = "Object")]
+    object: String,
+    #[serde(alias = "Id", alias = "id")]
+    pub uuid: Uuid,
+    #[serde(alias = "Name")]
+    pub name: String,
+    #[serde(alias = "Key")]
+    pub key: Option<CipherString>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Folder {
     #[serde(alias = "Object")]
     object: String,
-    #[serde(alias = "Id")]
+    #[serde(alias = "Id", alias = "id")]
     pub uuid: Uuid,
     #[serde(alias = "Name")]
     pub name: CipherString,
-    #[serde(alias = "RevisionDate")]
+    #[serde(alias = "RevisionDate", alias = "revisionDate")]
     pub last_changed: DateTime<Utc>,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Collection {
+    #[serde(alias = "Object")]
+    object: String,
+    #[serde(alias = "Id", alias = "id")]
+    pub uuid: Uuid,
+    #[serde(alias = "OrganizationId", alias = "organizationId")]
+    pub organization_id: Uuid,
+    #[serde(alias = "Name")]
+    pub name: CipherString,
+    #[serde(default, alias = "ReadOnly", alias = "readOnly")]
+    pub read_only: bool,
+}
+
+/// Custom field of an item. Type 0 is plain text, 1 is hidden, 2 is a boolean and 3 is linked
+/// to another field of the item.
+///
+/// Like all parts of an item, it is serialized the way the server sends it, so stored items can
+/// be sent back when editing them. The snake_case aliases read data stored by older versions.
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct CipherEntryFields {
-    #[serde(alias = "Type")]
+pub struct CipherEntryFields<S = CipherString> {
+    #[serde(rename = "type", alias = "Type", alias = "type_")]
     pub type_: usize,
     #[serde(alias = "Name")]
-    pub name: CipherString,
+    pub name: Option<S>,
     #[serde(alias = "Value")]
-    pub value: CipherString,
+    pub value: Option<S>,
+    /// Values unknown to this client, like the field a linked field refers to.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl<S> CipherEntryFields<S> {
+    pub fn is_hidden(&self) -> bool {
+        self.type_ == 1
+    }
+}
+
+impl Decrypt for CipherEntryFields {
+    type Output = CipherEntryFields<SecretString>;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        Some(CipherEntryFields {
+            type_: self.type_,
+            name: self.name.decrypt(key)?,
+            value: self.value.decrypt(key)?,
+            other: self.other.clone(),
+        })
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct CipherEntryHistory {
+pub struct CipherEntryHistory<S = CipherString> {
     #[serde(alias = "Password")]
-    pub password: String,
-    #[serde(alias = "LastUsedDate")]
+    pub password: S,
+    #[serde(rename = "lastUsedDate", alias = "LastUsedDate", alias = "last_used_date")]
     pub last_used_date: DateTime<Utc>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct CipherEntryUriMatch {
+impl Decrypt for CipherEntryHistory {
+    type Output = CipherEntryHistory<SecretString>;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        Some(CipherEntryHistory {
+            password: self.password.decrypt(key)?,
+            last_used_date: self.last_used_date,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CipherEntryUriMatch<S = CipherString> {
     #[serde(alias = "Uri")]
-    pub uri: CipherString,
-    #[serde(alias = "Match")]
+    pub uri: Option<S>,
+    #[serde(rename = "match", alias = "Match", alias = "match_")]
     pub match_: Option<usize>,
+    /// Values unknown to this client, like the checksum of the URI.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+impl Decrypt for CipherEntryUriMatch {
+    type Output = CipherEntryUriMatch<SecretString>;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        Some(CipherEntryUriMatch {
+            uri: self.uri.decrypt(key)?,
+            match_: self.match_,
+            other: self.other.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CipherType {
+    Login = 1,
+    SecureNote = 2,
+    Card = 3,
+    Identity = 4,
+}
+
+impl CipherType {
+    pub fn from_id(id: usize) -> Option<Self> {
+        match id {
+            1 => Some(Self::Login),
+            2 => Some(Self::SecureNote),
+            3 => Some(Self::Card),
+            4 => Some(Self::Identity),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CipherType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Login => "login",
+            Self::SecureNote => "note",
+            Self::Card => "card",
+            Self::Identity => "identity",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Type-specific data of a login item. Encrypted as received from the server by default, or
+/// decrypted with `S = SecretString`, which zeroizes the values once dropped.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Login<S = CipherString> {
+    #[serde(alias = "Uris")]
+    pub uris: Option<Vec<CipherEntryUriMatch<S>>>,
+    #[serde(alias = "Username")]
+    pub username: Option<S>,
+    #[serde(alias = "Password")]
+    pub password: Option<S>,
+    #[serde(
+        rename = "passwordRevisionDate",
+        alias = "PasswordRevisionDate",
+        alias = "password_last_changed"
+    )]
+    pub password_last_changed: Option<DateTime<Utc>>,
+    #[serde(alias = "Totp")]
+    pub totp: Option<S>,
+    /// Values unknown to this client, like passkeys.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+// Not derived, as that would require `S: Default`
+impl<S> Default for Login<S> {
+    fn default() -> Self {
+        Self {
+            uris: None,
+            username: None,
+            password: None,
+            password_last_changed: None,
+            totp: None,
+            other: HashMap::new(),
+        }
+    }
+}
+
+impl Decrypt for Login {
+    type Output = Login<SecretString>;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        Some(Login {
+            uris: self.uris.decrypt(key)?,
+            username: self.username.decrypt(key)?,
+            password: self.password.decrypt(key)?,
+            password_last_changed: self.password_last_changed,
+            totp: self.totp.decrypt(key)?,
+            other: self.other.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SecureNote {
+    #[serde(rename = "type", alias = "Type", alias = "type_")]
+    pub type_: usize,
+}
+
+/// Type-specific data of a card item, see [`Login`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Card<S = CipherString> {
+    #[serde(rename = "cardholderName", alias = "CardholderName", alias = "cardholder_name")]
+    pub cardholder_name: Option<S>,
+    #[serde(alias = "Brand")]
+    pub brand: Option<S>,
+    #[serde(alias = "Number")]
+    pub number: Option<S>,
+    #[serde(rename = "expMonth", alias = "ExpMonth", alias = "exp_month")]
+    pub exp_month: Option<S>,
+    #[serde(rename = "expYear", alias = "ExpYear", alias = "exp_year")]
+    pub exp_year: Option<S>,
+    #[serde(alias = "Code")]
+    pub code: Option<S>,
+}
+
+impl Decrypt for Card {
+    type Output = Card<SecretString>;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        Some(Card {
+            cardholder_name: self.cardholder_name.decrypt(key)?,
+            brand: self.brand.decrypt(key)?,
+            number: self.number.decrypt(key)?,
+            exp_month: self.exp_month.decrypt(key)?,
+            exp_year: self.exp_year.decrypt(key)?,
+            code: self.code.decrypt(key)?,
+        })
+    }
 }
 
+/// Type-specific data of an identity item, see [`Login`].
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Identity<S = CipherString> {
+    #[serde(alias = "Title")]
+    pub title: Option<S>,
+    #[serde(rename = "firstName", alias = "FirstName", alias = "first_name")]
+    pub first_name: Option<S>,
+    #[serde(rename = "middleName", alias = "MiddleName", alias = "middle_name")]
+    pub middle_name: Option<S>,
+    #[serde(rename = "lastName", alias = "LastName", alias = "last_name")]
+    pub last_name: Option<S>,
+    #[serde(alias = "Address1")]
+    pub address1: Option<S>,
+    #[serde(alias = "Address2")]
+    pub address2: Option<S>,
+    #[serde(alias = "Address3")]
+    pub address3: Option<S>,
+    #[serde(alias = "City")]
+    pub city: Option<S>,
+    #[serde(alias = "State")]
+    pub state: Option<S>,
+    #[serde(rename = "postalCode", alias = "PostalCode", alias = "postal_code")]
+    pub postal_code: Option<S>,
+    #[serde(alias = "Country")]
+    pub country: Option<S>,
+    #[serde(alias = "Company")]
+    pub company: Option<S>,
+    #[serde(alias = "Email")]
+    pub email: Option<S>,
+    #[serde(alias = "Phone")]
+    pub phone: Option<S>,
+    #[serde(alias = "SSN")]
+    pub ssn: Option<S>,
+    #[serde(alias = "Username")]
+    pub username: Option<S>,
+    #[serde(rename = "passportNumber", alias = "PassportNumber", alias = "passport_number")]
+    pub passport_number: Option<S>,
+    #[serde(rename = "licenseNumber", alias = "LicenseNumber", alias = "license_number")]
+    pub license_number: Option<S>,
+}
+
+impl Decrypt for Identity {
+    type Output = Identity<SecretString>;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        Some(Identity {
+            title: self.title.decrypt(key)?,
+            first_name: self.first_name.decrypt(key)?,
+            middle_name: self.middle_name.decrypt(key)?,
+            last_name: self.last_name.decrypt(key)?,
+            address1: self.address1.decrypt(key)?,
+            address2: self.address2.decrypt(key)?,
+            address3: self.address3.decrypt(key)?,
+            city: self.city.decrypt(key)?,
+            state: self.state.decrypt(key)?,
+            postal_code: self.postal_code.decrypt(key)?,
+            country: self.country.decrypt(key)?,
+            company: self.company.decrypt(key)?,
+            email: self.email.decrypt(key)?,
+            phone: self.phone.decrypt(key)?,
+            ssn: self.ssn.decrypt(key)?,
+            username: self.username.decrypt(key)?,
+            passport_number: self.passport_number.decrypt(key)?,
+            license_number: self.license_number.decrypt(key)?,
+        })
+    }
+}
+
+/// Decrypted type-specific data of a vault item.
+#[derive(Clone, Debug)]
+pub enum ItemData {
+    Login(Login<SecretString>),
+    SecureNote,
+    Card(Card<SecretString>),
+    Identity(Identity<SecretString>),
+}
+
+/// Legacy blob of item data, which older servers send instead of the type-specific objects.
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CipherEntryData {
     #[serde(alias = "Uri")]
@@ -116,20 +500,20 @@ pub struct CipherEntryData {
     #[serde(alias = "Uris")]
     pub uris: Option<Vec<CipherEntryUriMatch>>,
     #[serde(alias = "Username")]
-    pub username: CipherString,
+    pub username: Option<CipherString>,
     #[serde(alias = "Password")]
-    pub password: CipherString,
-    #[serde(alias = "PasswordRevisionDate")]
-    pub assword_last_changed: Option<DateTime<Utc>>,
+    pub password: Option<CipherString>,
+    #[serde(alias = "PasswordRevisionDate", alias = "passwordRevisionDate")]
+    pub password_last_changed: Option<DateTime<Utc>>,
     #[serde(alias = "Totp")]
-    pub totp: Option<String>,
+    pub totp: Option<CipherString>,
     #[serde(alias = "Name")]
     pub name: CipherString,
     #[serde(alias = "Notes")]
-    pub notes: Option<String>,
+    pub notes: Option<CipherString>,
     #[serde(alias = "Fields")]
     pub fields: Option<Vec<CipherEntryFields>>,
-    #[serde(alias = "PasswordHistory")]
+    #[serde(alias = "PasswordHistory", alias = "passwordHistory")]
     pub password_history: Option<Vec<CipherEntryHistory>>,
 }
 
@@ -137,44 +521,368 @@ pub struct CipherEntryData {
 pub struct CipherEntry {
     #[serde(alias = "Object")]
     object: String,
-    #[serde(alias = "CollectionIds")]
+    // Only sent as part of the sync
+    #[serde(default, alias = "CollectionIds", alias = "collectionIds")]
     pub collection_ids: Vec<Uuid>,
-    #[serde(alias = "FolderId")]
+    #[serde(alias = "FolderId", alias = "folderId")]
     pub folder_id: Option<Uuid>,
     #[serde(alias = "Favorite")]
     pub favorite: bool,
     #[serde(alias = "Edit")]
     pub edit: bool,
-    #[serde(alias = "Id")]
+    #[serde(alias = "Id", alias = "id")]
     pub uuid: Uuid,
-    #[serde(alias = "OrganizationId")]
+    #[serde(alias = "OrganizationId", alias = "organizationId")]
     pub organization_id: Option<Uuid>,
-    #[serde(alias = "Type")]
+    #[serde(alias = "Type", alias = "type")]
     pub type_: usize,
     #[serde(alias = "Data")]
-    pub data: CipherEntryData,
+    pub data: Option<CipherEntryData>,
     #[serde(alias = "Name")]
     pub name: CipherString,
     #[serde(alias = "Notes")]
-    pub notes: Option<String>,
-    #[serde(alias = "Login", skip)]
-    pub login: Option<CipherEntryData>,
+    pub notes: Option<CipherString>,
+    #[serde(alias = "Login")]
+    pub login: Option<Login>,
     #[serde(alias = "Card")]
-    pub card: Option<String>,
+    pub card: Option<Card>,
     #[serde(alias = "Identity")]
-    pub identity: Option<String>,
-    #[serde(alias = "SecureNote")]
-    pub secure_note: Option<String>,
+    pub identity: Option<Identity>,
+    #[serde(alias = "SecureNote", alias = "secureNote")]
+    pub secure_note: Option<SecureNote>,
     #[serde(alias = "Fields")]
     pub fields: Option<Vec<CipherEntryFields>>,
-    #[serde(alias = "PasswordHistory")]
+    #[serde(alias = "PasswordHistory", alias = "passwordHistory")]
     pub password_history: Option<Vec<CipherEntryHistory>>,
-    #[serde(alias = "Attachments")]
-    pub attachments: Option<String>,
-    #[serde(alias = "OrganizationUseTotp")]
+    #[serde(alias = "OrganizationUseTotp", alias = "organizationUseTotp")]
     pub organization_tfa: bool,
-    #[serde(alias = "RevisionDate")]
+    /// Key of this item only, encrypted with the user or organization key. Items created or
+    /// updated by recent clients are encrypted with it.
+    #[serde(default, alias = "Key", alias = "key")]
+    pub item_key: Option<CipherString>,
+    #[serde(alias = "RevisionDate", alias = "revisionDate")]
     pub last_changed: DateTime<Utc>,
+    /// Values unknown to this client, like whether the master password is asked for again.
+    /// They are sent back unchanged when editing the item.
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
+}
+
+/// Item as sent to the server to create or update it. Only the values the server accepts are
+/// sent, values of the item unknown to this client just by name (see `passthrough`).
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CipherRequest<'a> {
+    #[serde(rename = "type")]
+    pub type_: usize,
+    pub organization_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+    pub favorite: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reprompt: Option<&'a Value>,
+    pub key: Option<&'a CipherString>,
+    pub name: &'a CipherString,
+    pub notes: Option<&'a CipherString>,
+    pub login: Option<CipherLoginRequest>,
+    pub card: Option<&'a Card>,
+    pub identity: Option<&'a Identity>,
+    pub secure_note: Option<&'a SecureNote>,
+    pub fields: Option<Vec<CipherFieldRequest<'a>>>,
+    pub password_history: Option<&'a Vec<CipherEntryHistory>>,
+    /// Lets the server refuse the update if the item was changed since.
+    pub last_known_revision_date: Option<DateTime<Utc>>,
+}
+
+/// Login data as sent to the server, see [`CipherRequest`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CipherLoginRequest {
+    pub uris: Option<Vec<CipherUriRequest>>,
+    pub username: Option<CipherString>,
+    pub password: Option<CipherString>,
+    pub password_revision_date: Option<DateTime<Utc>>,
+    pub totp: Option<CipherString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub autofill_on_page_load: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fido2_credentials: Option<Value>,
+}
+
+/// URI of a login as sent to the server, see [`CipherRequest`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CipherUriRequest {
+    pub uri: Option<CipherString>,
+    #[serde(rename = "match")]
+    pub match_: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri_checksum: Option<Value>,
+}
+
+/// Custom field as sent to the server, see [`CipherRequest`].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CipherFieldRequest<'a> {
+    #[serde(rename = "type")]
+    pub type_: usize,
+    pub name: Option<&'a CipherString>,
+    pub value: Option<&'a CipherString>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked_id: Option<&'a Value>,
+}
+
+/// Request to create an item shared with an organization, which must be in a collection.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CreateSharedCipherRequest<'a> {
+    pub cipher: CipherRequest<'a>,
+    pub collection_ids: &'a [Uuid],
+}
+
+/// Values of an item which can be edited. Empty values are removed from the item, and the login
+/// values are only used for logins.
+#[derive(Clone, Debug, Default)]
+pub struct ItemEdit {
+    pub name: String,
+    pub notes: SecretString,
+    pub favorite: bool,
+    pub username: String,
+    pub password: SecretString,
+    pub uris: Vec<String>,
+}
+
+// Number of previous passwords the official clients keep
+const PASSWORD_HISTORY_LENGTH: usize = 5;
+
+fn encrypt_non_empty(value: &str, key: &SymmetricKey) -> Option<CipherString> {
+    Some(value)
+        .filter(|value| !value.is_empty())
+        .map(|value| CipherString::encrypt(value.as_bytes(), key))
+}
+
+/// Returns a value unknown to this client, which is sent back unchanged when saving the item.
+/// The server sends them with the first letter in upper case as well.
+fn passthrough<'a>(other: &'a HashMap<String, Value>, name: &str) -> Option<&'a Value> {
+    other.get(name).or_else(|| {
+        let mut chars = name.chars();
+        let first = chars.next()?.to_ascii_uppercase();
+        other.get(&format!("{}{}", first, chars.as_str()))
+    })
+}
+
+impl CipherEntryData {
+    fn login(&self) -> Login {
+        // Only the oldest servers know a single URI per item
+        let uris = self.uris.clone().or_else(|| {
+            let uri = self.uri.clone()?;
+            Some(vec![CipherEntryUriMatch {
+                uri: Some(uri),
+                match_: None,
+                other: HashMap::new(),
+            }])
+        });
+
+        Login {
+            uris,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            password_last_changed: self.password_last_changed,
+            totp: self.totp.clone(),
+            other: HashMap::new(),
+        }
+    }
+}
+
+impl CipherEntry {
+    /// Creates a login item, encrypted with the user key or the key of the given organization,
+    /// which also requires setting `collection_ids`. The server assigns its id once created.
+    pub fn new_login(
+        edit: &ItemEdit,
+        organization: Option<Uuid>,
+        cipher: &CipherSuite,
+    ) -> Result<Self, CipherError> {
+        let key = cipher.key_for(organization).ok_or(CipherError::Locked)?;
+
+        let mut entry = Self {
+            object: "cipher".to_owned(),
+            collection_ids: Vec::new(),
+            folder_id: None,
+            favorite: false,
+            edit: true,
+            uuid: Uuid::nil(),
+            organization_id: organization,
+            type_: CipherType::Login as usize,
+            data: None,
+            name: CipherString::encrypt(edit.name.as_bytes(), key),
+            notes: None,
+            login: None,
+            card: None,
+            identity: None,
+            secure_note: None,
+            fields: None,
+            password_history: None,
+            organization_tfa: false,
+            item_key: None,
+            last_changed: Utc::now(),
+            other: HashMap::new(),
+        };
+
+        entry.apply_edit(edit, cipher)?;
+        Ok(entry)
+    }
+
+    /// Replaces the edited values, encrypting them with the item's key (see `key`). All other
+    /// values are kept as they are. Like the official clients do, a changed password is added to
+    /// the password history.
+    pub fn apply_edit(&mut self, edit: &ItemEdit, cipher: &CipherSuite) -> Result<(), CipherError> {
+        let key = self.key(cipher).ok_or(CipherError::Locked)?;
+        let key = &*key;
+
+        self.name = CipherString::encrypt(edit.name.as_bytes(), key);
+        self.notes = encrypt_non_empty(&edit.notes, key);
+        self.favorite = edit.favorite;
+
+        if self.cipher_type() != Some(CipherType::Login) {
+            return Ok(());
+        }
+
+        let mut login = self.login().unwrap_or_default();
+        let old_uris = login.uris.take().unwrap_or_default();
+
+        // Unchanged URIs keep their match setting and other values, like their checksum
+        let uris: Vec<_> = edit
+            .uris
+            .iter()
+            .filter(|uri| !uri.is_empty())
+            .map(|uri| {
+                let old = old_uris.iter().find(|old| {
+                    old.uri.decrypt(key).flatten().is_some_and(|old| *old == **uri)
+                });
+
+                match old {
+                    Some(old) => old.clone(),
+                    None => CipherEntryUriMatch {
+                        uri: Some(CipherString::encrypt(uri.as_bytes(), key)),
+                        match_: None,
+                        other: HashMap::new(),
+                    },
+                }
+            })
+            .collect();
+
+        let old_password = login.password.decrypt(key).flatten().unwrap_or_default();
+
+        if *old_password != *edit.password {
+            if let Some(password) = login.password.take() {
+                let history = self.password_history.get_or_insert_with(Vec::new);
+                history.insert(0, CipherEntryHistory { password, last_used_date: Utc::now() });
+                history.truncate(PASSWORD_HISTORY_LENGTH);
+
+                login.password_last_changed = Some(Utc::now());
+            }
+
+            login.password = encrypt_non_empty(&edit.password, key);
+        }
+
+        login.uris = Some(uris).filter(|uris| !uris.is_empty());
+        login.username = encrypt_non_empty(&edit.username, key);
+        self.login = Some(login);
+
+        Ok(())
+    }
+
+    pub(crate) fn to_request(&self, check_revision: bool) -> CipherRequest<'_> {
+        // The legacy data blob is not specific to logins, so only logins get login data
+        let login = self.login().filter(|_| self.cipher_type() == Some(CipherType::Login));
+        let login = login.map(|login| CipherLoginRequest {
+            uris: login.uris.map(|uris| {
+                uris.into_iter()
+                    .map(|uri| CipherUriRequest {
+                        uri_checksum: passthrough(&uri.other, "uriChecksum").cloned(),
+                        uri: uri.uri,
+                        match_: uri.match_,
+                    })
+                    .collect()
+            }),
+            username: login.username,
+            password: login.password,
+            password_revision_date: login.password_last_changed,
+            totp: login.totp,
+            autofill_on_page_load: passthrough(&login.other, "autofillOnPageLoad").cloned(),
+            fido2_credentials: passthrough(&login.other, "fido2Credentials").cloned(),
+        });
+
+        let fields = self.fields.as_ref().map(|fields| {
+            fields
+                .iter()
+                .map(|field| CipherFieldRequest {
+                    type_: field.type_,
+                    name: field.name.as_ref(),
+                    value: field.value.as_ref(),
+                    linked_id: passthrough(&field.other, "linkedId"),
+                })
+                .collect()
+        });
+
+        CipherRequest {
+            type_: self.type_,
+            organization_id: self.organization_id,
+            folder_id: self.folder_id,
+            favorite: self.favorite,
+            reprompt: passthrough(&self.other, "reprompt"),
+            key: self.item_key.as_ref(),
+            name: &self.name,
+            notes: self.notes.as_ref(),
+            login,
+            card: self.card.as_ref(),
+            identity: self.identity.as_ref(),
+            secure_note: self.secure_note.as_ref(),
+            fields,
+            password_history: self.password_history.as_ref(),
+            last_known_revision_date: Some(self.last_changed).filter(|_| check_revision),
+        }
+    }
+
+    pub fn cipher_type(&self) -> Option<CipherType> {
+        CipherType::from_id(self.type_)
+    }
+
+    /// Returns the login data, from the legacy data blob if the server did not send the `login`
+    /// object.
+    pub fn login(&self) -> Option<Login> {
+        self.login.clone().or_else(|| self.data.as_ref().map(CipherEntryData::login))
+    }
+
+    /// Returns the key the item is encrypted with. This is its own key if it has one, otherwise
+    /// the organization key for shared items or the user key.
+    pub fn key<'a>(&self, cipher: &'a CipherSuite) -> Option<Cow<'a, SymmetricKey>> {
+        let key = cipher.key_for(self.organization_id)?;
+
+        match &self.item_key {
+            Some(item_key) => key.unwrap_key(item_key).ok().map(Cow::Owned),
+            None => Some(Cow::Borrowed(key)),
+        }
+    }
+
+    /// Decrypts the data specific to the item's type. Missing data is treated as empty.
+    pub fn decrypt_data(&self, key: &SymmetricKey) -> Option<ItemData> {
+        fn decrypt_or_default<T>(data: &Option<T>, key: &SymmetricKey) -> Option<T::Output>
+        where
+            T: Decrypt,
+            T::Output: Default,
+        {
+            data.as_ref().map_or_else(|| Some(Default::default()), |d| d.decrypt(key))
+        }
+
+        match self.cipher_type()? {
+            CipherType::Login => decrypt_or_default(&self.login(), key).map(ItemData::Login),
+            CipherType::SecureNote => Some(ItemData::SecureNote),
+            CipherType::Card => decrypt_or_default(&self.card, key).map(ItemData::Card),
+            CipherType::Identity => {
+                decrypt_or_default(&self.identity, key).map(ItemData::Identity)
+            }
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -186,7 +894,7 @@ pub struct SyncResponse {
     #[serde(alias = "Folders")]
     pub folders: Vec<Folder>,
     #[serde(alias = "Collections")]
-    pub collections: Vec<String>,
+    pub collections: Vec<Collection>,
     #[serde(alias = "Ciphers")]
     pub ciphers: Vec<CipherEntry>,
 }
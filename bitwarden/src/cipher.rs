@@ -1,160 +1,713 @@
 // SPDX-License-Identifier: MIT
 
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
 
-use aes::Aes256;
+use aes::{Aes128, Aes256};
+use argon2::{Algorithm, Argon2, Params, Version};
 use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
 use hkdf::Hkdf;
+use hmac::crypto_mac::Output;
 use hmac::{Hmac, Mac, NewMac};
 use pbkdf2::pbkdf2;
+use rsa::{PaddingScheme, RSAPrivateKey};
 use serde::de::Visitor;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use sha2::Sha256;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use zeroize::Zeroize;
 
-#[derive(Debug, Default)]
+use crate::api_definition::CipherEntry;
+
+#[derive(Default)]
 pub struct CipherSuite {
-    master_key: Vec<u8>,
-    pub master_key_hash: String,
-    mac_key: Vec<u8>,
+    master_key: SecretBytes,
+    pub master_key_hash: SecretString,
+
+    user_key: Option<SymmetricKey>,
+    private_key: Option<RSAPrivateKey>,
+    organization_keys: HashMap<Uuid, SymmetricKey>,
+}
+
+impl fmt::Debug for CipherSuite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CipherSuite")
+            .field("user_key", &self.user_key.is_some())
+            .field("private_key", &self.private_key.is_some())
+            .field("organizations", &self.organization_keys.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+/// AES-256 encryption key along with its HMAC-SHA256 key, as used for the user, organization and
+/// item keys. Keys of legacy accounts have no mac key.
+#[derive(Clone, Debug)]
+pub struct SymmetricKey {
+    enc: SecretBytes,
+    mac: Option<SecretBytes>,
+}
+
+impl SymmetricKey {
+    /// Splits 64 bytes of key material into the encryption and the mac key, or uses 32 bytes as
+    /// encryption key only.
+    pub fn from_bytes(key: &[u8]) -> Result<Self, CipherError> {
+        match key.len() {
+            32 => Ok(Self { enc: SecretBytes::from(key), mac: None }),
+            64 => Ok(Self {
+                enc: SecretBytes::from(&key[0..32]),
+                mac: Some(SecretBytes::from(&key[32..64])),
+            }),
+            _ => Err(CipherError::InvalidKeyLength),
+        }
+    }
+
+    /// Decrypts a key which is encrypted with this one, like the key of a single item.
+    pub fn unwrap_key(&self, key: &CipherString) -> Result<SymmetricKey, CipherError> {
+        let key = key.decrypt_raw(self).map(SecretBytes::from)?;
+        Self::from_bytes(&key)
+    }
+
+    /// The encryption key followed by the mac key, if any, the inverse of `from_bytes`.
+    pub fn to_bytes(&self) -> SecretBytes {
+        match &self.mac {
+            Some(mac) => SecretBytes([&self.enc[..], &mac[..]].concat()),
+            None => self.enc.clone(),
+        }
+    }
+
+    /// Derives an independent key for the given purpose, so data only this client stores is not
+    /// encrypted with the same key as the vault.
+    pub fn derive(&self, purpose: &str) -> SymmetricKey {
+        let mut key = SecretBytes(vec![0u8; 64]);
+
+        Hkdf::<Sha256>::new(None, &self.to_bytes())
+            .expand(purpose.as_bytes(), &mut key.0)
+            .unwrap();
+
+        Self::from_bytes(&key).unwrap()
+    }
+}
+
+/// Bytes of key material or plaintext, zeroized when dropped.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretBytes(Vec<u8>);
+
+impl Deref for SecretBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for SecretBytes {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretBytes(<redacted>)")
+    }
+}
+
+/// Decrypted text like a password, zeroized when dropped.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl Deref for SecretString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(text: String) -> Self {
+        Self(text)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(text: &str) -> Self {
+        Self(text.to_owned())
+    }
+}
+
+impl PartialEq<str> for SecretString {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for SecretString {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SecretString(<redacted>)")
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
 
-    decrypt_key: Option<Vec<u8>>,
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).map(Self)
+    }
 }
 
-#[derive(Debug, failure::Fail)]
+#[derive(Debug, thiserror::Error)]
 pub enum CipherError {
-    #[fail(display = "failed to verify key")]
+    #[error("failed to verify key")]
     InvalidMac,
 
-    #[fail(display = "only type 2 ciphers are supported")]
+    #[error("unsupported cipher type")]
     InvalidKeyType,
 
-    #[fail(display = "key length must  be exactly 32 bytes")]
+    #[error("invalid key length")]
     InvalidKeyLength,
 
-    #[fail(display = "block mode error")]
+    #[error("block mode error")]
     BlockModeError,
 
-    #[fail(display = "failed to set decrypt key: {:?}", 0)]
+    #[error("failed to set decrypt key: {0}")]
     DecryptionKeyError(String),
+
+    #[error("unsupported key derivation function: {0}")]
+    UnsupportedKdf(String),
+
+    #[error("invalid cipher string: {0}")]
+    InvalidFormat(String),
+
+    #[error("missing mac")]
+    MissingMac,
+
+    #[error("vault is locked")]
+    Locked,
+}
+
+/// Key derivation function used to derive the master key from the master password.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "KdfParams", into = "KdfParams")]
+pub enum Kdf {
+    Pbkdf2 {
+        iterations: u32,
+    },
+    Argon2id {
+        iterations: u32,
+        /// Memory size in MiB.
+        memory: u32,
+        parallelism: u32,
+    },
+}
+
+// Default Argon2id parameters of the official clients, used if the server omits them
+const ARGON2_DEFAULT_MEMORY: u32 = 64;
+const ARGON2_DEFAULT_PARALLELISM: u32 = 4;
+
+/// Wire format of the KDF settings, as sent by the server on prelogin and stored along with the
+/// auth data.
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct KdfParams {
+    #[serde(alias = "Kdf")]
+    kdf: u32,
+    #[serde(alias = "KdfIterations", alias = "kdfIterations")]
+    kdf_iterations: u32,
+    #[serde(default, alias = "KdfMemory", alias = "kdfMemory")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf_memory: Option<u32>,
+    #[serde(default, alias = "KdfParallelism", alias = "kdfParallelism")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kdf_parallelism: Option<u32>,
+}
+
+impl TryFrom<KdfParams> for Kdf {
+    type Error = CipherError;
+
+    fn try_from(params: KdfParams) -> Result<Self, Self::Error> {
+        match params.kdf {
+            0 => Ok(Kdf::Pbkdf2 {
+                iterations: params.kdf_iterations,
+            }),
+            1 => {
+                let iterations = params.kdf_iterations;
+                let memory = params.kdf_memory.unwrap_or(ARGON2_DEFAULT_MEMORY);
+                let parallelism = params.kdf_parallelism.unwrap_or(ARGON2_DEFAULT_PARALLELISM);
+
+                argon2_params(iterations, memory, parallelism)?;
+                Ok(Kdf::Argon2id { iterations, memory, parallelism })
+            }
+            other => Err(CipherError::UnsupportedKdf(format!("type {}", other))),
+        }
+    }
+}
+
+impl From<Kdf> for KdfParams {
+    fn from(kdf: Kdf) -> Self {
+        match kdf {
+            Kdf::Pbkdf2 { iterations } => KdfParams {
+                kdf: 0,
+                kdf_iterations: iterations,
+                kdf_memory: None,
+                kdf_parallelism: None,
+            },
+            Kdf::Argon2id { iterations, memory, parallelism } => KdfParams {
+                kdf: 1,
+                kdf_iterations: iterations,
+                kdf_memory: Some(memory),
+                kdf_parallelism: Some(parallelism),
+            },
+        }
+    }
+}
+
+fn argon2_params(iterations: u32, memory: u32, parallelism: u32) -> Result<Params, CipherError> {
+    let memory_kib = memory.checked_mul(1024).unwrap_or(u32::MAX);
+
+    Params::new(memory_kib, iterations, parallelism, Some(32))
+        .map_err(|err| CipherError::UnsupportedKdf(err.to_string()))
 }
 
 impl CipherSuite {
-    pub fn from(email: &str, password: &str, kdf_iterations: u32) -> Self {
-        let (master_key, master_key_hash, mac_key) =
-            derive_master_key(email, password, kdf_iterations);
+    /// Derives the master key. Fails for KDF parameters which are not accepted, like Argon2id
+    /// with too little memory.
+    pub fn from(email: &str, password: &str, kdf: Kdf) -> Result<Self, CipherError> {
+        let (master_key, master_key_hash) = derive_master_key(email, password, kdf)?;
 
-        Self {
+        Ok(Self {
             master_key,
             master_key_hash,
-            mac_key,
-            decrypt_key: None,
-        }
+            ..Default::default()
+        })
     }
 
     pub fn set_decrypt_key(&mut self, key: &CipherString) -> Result<(), CipherError> {
+        // User keys of legacy accounts are encrypted with the master key itself, without mac
+        let master_key = match key.encryption_type() {
+            EncryptionType::AesCbc256B64 => SymmetricKey::from_bytes(&self.master_key)?,
+            _ => stretch_master_key(&self.master_key),
+        };
+
         let key = key
-            .decrypt_raw(&self.master_key, &self.mac_key)
+            .decrypt_raw(&master_key)
+            .map(SecretBytes::from)
+            .map_err(|e| CipherError::DecryptionKeyError(e.to_string()))?;
+
+        self.user_key = Some(SymmetricKey::from_bytes(&key)?);
+
+        Ok(())
+    }
+
+    /// Sets the already decrypted user key, e.g. one handed over by another process, instead of
+    /// decrypting it with the master key.
+    pub fn set_user_key(&mut self, key: SymmetricKey) {
+        self.user_key = Some(key);
+    }
+
+    /// Sets the user's RSA private key, which is encrypted with the user key and wraps the keys
+    /// of all organizations the user is a member of.
+    pub fn set_private_key(&mut self, key: &CipherString) -> Result<(), CipherError> {
+        let user_key = self.user_key.as_ref().ok_or_else(|| {
+            CipherError::DecryptionKeyError("user key not available".to_owned())
+        })?;
+
+        let der = key
+            .decrypt_raw(user_key)
+            .map(SecretBytes::from)
+            .map_err(|e| CipherError::DecryptionKeyError(e.to_string()))?;
+
+        let private_key = RSAPrivateKey::from_pkcs8(&der)
             .map_err(|e| CipherError::DecryptionKeyError(e.to_string()))?;
 
-        self.decrypt_key = Some(Vec::from(&key[0..32]));
-        self.mac_key = Vec::from(&key[32..64]);
+        self.private_key = Some(private_key);
 
         Ok(())
     }
+
+    /// Replaces the organization keys with the given RSA-wrapped keys. Keys which cannot be
+    /// unwrapped are skipped, returning the organizations they belong to.
+    pub fn set_organization_keys<'a, I>(&mut self, keys: I) -> Result<Vec<Uuid>, CipherError>
+    where
+        I: IntoIterator<Item = (Uuid, &'a CipherString)>,
+    {
+        let private_key = self.private_key.as_ref().ok_or_else(|| {
+            CipherError::DecryptionKeyError("private key not available".to_owned())
+        })?;
+
+        let mut skipped = Vec::new();
+        self.organization_keys.clear();
+
+        for (uuid, key) in keys {
+            let key = key
+                .decrypt_rsa(private_key)
+                .map(SecretBytes::from)
+                .and_then(|key| SymmetricKey::from_bytes(&key));
+
+            match key {
+                Ok(key) => {
+                    self.organization_keys.insert(uuid, key);
+                }
+                Err(_) => skipped.push(uuid),
+            }
+        }
+
+        Ok(skipped)
+    }
+
+    /// Returns the key items of the given organization, or the user's own items if `None`, are
+    /// encrypted with.
+    pub fn key_for(&self, organization: Option<Uuid>) -> Option<&SymmetricKey> {
+        match organization {
+            Some(uuid) => self.organization_keys.get(&uuid),
+            None => self.user_key.as_ref(),
+        }
+    }
+
+    /// Encrypts a value with the user key, the same way the official clients do. Values of items
+    /// must be encrypted using `encrypt_for` instead.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<CipherString, CipherError> {
+        let key = self.user_key.as_ref().ok_or(CipherError::Locked)?;
+        Ok(CipherString::encrypt(plaintext, key))
+    }
+
+    /// Encrypts a value of the item with the key it is encrypted with (see `CipherEntry::key`),
+    /// which is its own key or the key of its organization if it has one.
+    pub fn encrypt_for(
+        &self,
+        entry: &CipherEntry,
+        plaintext: &[u8],
+    ) -> Result<CipherString, CipherError> {
+        let key = entry.key(self).ok_or(CipherError::Locked)?;
+        Ok(CipherString::encrypt(plaintext, &key))
+    }
 }
 
-fn derive_master_key(email: &str, password: &str, iter_count: u32) -> (Vec<u8>, String, Vec<u8>) {
-    let mut master_key = vec![0u8; 32];
-    pbkdf2::<Hmac<Sha256>>(
-        password.as_bytes(),
-        email.as_bytes(),
-        iter_count,
-        &mut master_key,
-    );
+fn derive_master_key(
+    email: &str,
+    password: &str,
+    kdf: Kdf,
+) -> Result<(SecretBytes, SecretString), CipherError> {
+    let mut master_key = SecretBytes(vec![0u8; 32]);
+
+    match kdf {
+        Kdf::Pbkdf2 { iterations } => {
+            pbkdf2::<Hmac<Sha256>>(
+                password.as_bytes(),
+                email.as_bytes(),
+                iterations,
+                &mut master_key.0,
+            );
+        }
+        Kdf::Argon2id { iterations, memory, parallelism } => {
+            let params = argon2_params(iterations, memory, parallelism)?;
+            let salt = Sha256::digest(email.as_bytes());
+
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(password.as_bytes(), &salt, &mut master_key.0)
+                .map_err(|err| CipherError::UnsupportedKdf(err.to_string()))?;
+        }
+    }
+
+    let mut master_key_hash = SecretBytes(vec![0u8; 32]);
+    pbkdf2::<Hmac<Sha256>>(&master_key, password.as_bytes(), 1, &mut master_key_hash.0);
 
-    let mut master_key_hash = [0u8; 32];
-    pbkdf2::<Hmac<Sha256>>(&master_key, password.as_bytes(), 1, &mut master_key_hash);
+    Ok((master_key, SecretString(base64::encode(&*master_key_hash))))
+}
 
-    // Expand master key
-    let hkdf = Hkdf::<Sha256>::from_prk(&master_key).unwrap();
-    hkdf.expand(b"enc", &mut master_key).unwrap();
+/// Expands the master key into the encryption and mac key the user key is encrypted with.
+fn stretch_master_key(master_key: &[u8]) -> SymmetricKey {
+    let hkdf = Hkdf::<Sha256>::from_prk(master_key).unwrap();
 
-    let mut mac_key = vec![0u8; 32];
-    hkdf.expand(b"mac", &mut mac_key).unwrap();
+    let mut enc = SecretBytes(vec![0u8; 32]);
+    hkdf.expand(b"enc", &mut enc.0).unwrap();
 
-    (master_key, base64::encode(&master_key_hash), mac_key)
+    let mut mac = SecretBytes(vec![0u8; 32]);
+    hkdf.expand(b"mac", &mut mac.0).unwrap();
+
+    SymmetricKey { enc, mac: Some(mac) }
+}
+
+/// Encryption scheme of a cipher string, as identified by its type prefix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionType {
+    AesCbc256B64 = 0,
+    AesCbc128HmacSha256B64 = 1,
+    AesCbc256HmacSha256B64 = 2,
+    Rsa2048OaepSha256B64 = 3,
+    Rsa2048OaepSha1B64 = 4,
+    Rsa2048OaepSha256HmacSha256B64 = 5,
+    Rsa2048OaepSha1HmacSha256B64 = 6,
+}
+
+impl EncryptionType {
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::AesCbc256B64),
+            1 => Some(Self::AesCbc128HmacSha256B64),
+            2 => Some(Self::AesCbc256HmacSha256B64),
+            3 => Some(Self::Rsa2048OaepSha256B64),
+            4 => Some(Self::Rsa2048OaepSha1B64),
+            5 => Some(Self::Rsa2048OaepSha256HmacSha256B64),
+            6 => Some(Self::Rsa2048OaepSha1HmacSha256B64),
+            _ => None,
+        }
+    }
+
+    fn is_rsa(self) -> bool {
+        (self as u8) >= 3
+    }
+
+    fn has_mac(self) -> bool {
+        !matches!(self, Self::AesCbc256B64 | Self::Rsa2048OaepSha256B64 | Self::Rsa2048OaepSha1B64)
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct CipherString {
-    type_: usize,
+    type_: EncryptionType,
 
+    // Empty for RSA-encrypted values
     iv: Vec<u8>,
     ct: Vec<u8>,
+    // Empty for types without mac
     mac: Vec<u8>,
 }
 
-impl CipherString {
-    fn from_str(text: &str) -> Option<CipherString> {
-        let type_end = text.find('.')?;
-        let type_ = text[0..type_end].parse::<usize>().ok()?;
+impl FromStr for CipherString {
+    type Err = CipherError;
+
+    /// Parses `<type>.<b64 iv>|<b64 ciphertext>|<b64 mac>`, where the parts present depend on the
+    /// type. RSA-encrypted values have no IV.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| CipherError::InvalidFormat(reason);
+
+        let (type_, body) = match text.split_once('.') {
+            Some((id, body)) => {
+                let type_ = id
+                    .parse()
+                    .ok()
+                    .and_then(EncryptionType::from_id)
+                    .ok_or_else(|| invalid(format!("unknown encryption type {}", id)))?;
+
+                (type_, body)
+            }
+            // Values of very old clients have no type prefix, it follows from the number of parts
+            None if text.split('|').count() == 3 => (EncryptionType::AesCbc128HmacSha256B64, text),
+            None => (EncryptionType::AesCbc256B64, text),
+        };
+
+        let mut parts = body
+            .split('|')
+            .map(|part| base64::decode(part).map_err(|err| invalid(format!("{}: {}", err, part))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let expected = match (type_.is_rsa(), type_.has_mac()) {
+            (false, true) => 3,
+            (false, false) | (true, true) => 2,
+            (true, false) => 1,
+        };
+
+        if parts.len() != expected {
+            return Err(invalid(format!(
+                "expected {} parts for type {}, got {}",
+                expected,
+                type_ as u8,
+                parts.len(),
+            )));
+        }
 
-        let mut parts = text[type_end + 1..].split('|');
+        let mac = if type_.has_mac() { parts.pop().unwrap_or_default() } else { Vec::new() };
+        let ct = parts.pop().unwrap_or_default();
+        let iv = parts.pop().unwrap_or_default();
 
-        let iv = base64::decode(parts.next()?).ok()?;
-        let ct = base64::decode(parts.next()?).ok()?;
-        let mac = base64::decode(parts.next()?).ok()?;
+        if !type_.is_rsa() && iv.len() != 16 {
+            return Err(invalid(format!("expected 16 bytes of IV, got {}", iv.len())));
+        }
+
+        if type_.has_mac() && mac.len() != 32 {
+            return Err(invalid(format!("expected 32 bytes of mac, got {}", mac.len())));
+        }
+
+        Ok(CipherString { type_, iv, ct, mac })
+    }
+}
+
+impl fmt::Display for CipherString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = [&self.iv, &self.ct, &self.mac]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .map(base64::encode)
+            .collect::<Vec<_>>();
 
-        Some(CipherString { type_, iv, ct, mac })
+        write!(f, "{}.{}", self.type_ as u8, parts.join("|"))
     }
+}
 
-    fn as_str(&self) -> String {
-        format!(
-            "{}.{}|{}|{}",
-            self.type_,
-            base64::encode(&self.iv),
-            base64::encode(&self.ct),
-            base64::encode(&self.mac),
-        )
+impl CipherString {
+    pub fn encryption_type(&self) -> EncryptionType {
+        self.type_
     }
 
-    fn is_valid_mac(&self, mac_key: &[u8]) -> bool {
-        if mac_key.len() != 32 {
-            return false;
+    /// Encrypts using AES-256-CBC with a random IV and authenticates the ciphertext using
+    /// HMAC-SHA256 (type 2). Like the official clients, legacy keys without mac key only encrypt
+    /// (type 0).
+    pub fn encrypt(plaintext: &[u8], key: &SymmetricKey) -> CipherString {
+        let mut iv = vec![0u8; 16];
+        getrandom::getrandom(&mut iv).expect("no random numbers available");
+
+        let ct = Cbc::<Aes256, Pkcs7>::new_var(&key.enc, &iv).unwrap().encrypt_vec(plaintext);
+
+        match &key.mac {
+            Some(mac_key) => {
+                let mac = compute_mac(mac_key, &iv, &ct).into_bytes().to_vec();
+                CipherString { type_: EncryptionType::AesCbc256HmacSha256B64, iv, ct, mac }
+            }
+            None => CipherString { type_: EncryptionType::AesCbc256B64, iv, ct, mac: Vec::new() },
         }
+    }
 
-        let mut message = Vec::<u8>::new();
-        message.extend(&self.iv);
-        message.extend(&self.ct);
+    fn verify_mac(&self, mac_key: &[u8]) -> Result<(), CipherError> {
+        let mut mac = Hmac::<Sha256>::new_varkey(mac_key).expect("hmac accepts keys of any length");
+        mac.update(&self.iv);
+        mac.update(&self.ct);
 
-        let mut mac = Hmac::<Sha256>::new_varkey(mac_key).unwrap();
-        mac.update(&message);
+        mac.verify(&self.mac).map_err(|_| CipherError::InvalidMac)
+    }
 
-        mac.verify(&self.mac).is_ok()
+    /// Decrypts symmetrically encrypted values (types 0 to 2).
+    pub fn decrypt_raw(&self, key: &SymmetricKey) -> Result<Vec<u8>, CipherError> {
+        let enc_key = match (self.type_, &key.mac) {
+            // Accepting values without mac for keys which have one would allow stripping it
+            (EncryptionType::AesCbc256B64, Some(_)) => return Err(CipherError::MissingMac),
+            (EncryptionType::AesCbc256B64, None) => &key.enc[..],
+            // The 32 bytes of key material are split into the AES-128 and the mac key
+            (EncryptionType::AesCbc128HmacSha256B64, None) if key.enc.len() == 32 => {
+                let (enc_key, mac_key) = key.enc.split_at(16);
+                self.verify_mac(mac_key)?;
+                enc_key
+            }
+            (EncryptionType::AesCbc256HmacSha256B64, Some(mac_key)) => {
+                self.verify_mac(mac_key)?;
+                &key.enc[..]
+            }
+            (type_, _) if type_.is_rsa() => return Err(CipherError::InvalidKeyType),
+            _ => return Err(CipherError::InvalidKeyLength),
+        };
+
+        let plaintext = match enc_key.len() {
+            16 => Cbc::<Aes128, Pkcs7>::new_var(enc_key, &self.iv).map(|c| c.decrypt_vec(&self.ct)),
+            _ => Cbc::<Aes256, Pkcs7>::new_var(enc_key, &self.iv).map(|c| c.decrypt_vec(&self.ct)),
+        };
+
+        plaintext
+            .map_err(|_| CipherError::InvalidKeyLength)?
+            .map_err(|_| CipherError::BlockModeError)
     }
 
-    pub fn decrypt_raw(&self, key: &[u8], mac: &[u8]) -> Result<Vec<u8>, CipherError> {
-        if self.type_ != 2 {
-            return Err(CipherError::InvalidKeyType);
-        }
+    /// Decrypts RSA-2048 OAEP ciphertexts (types 3 to 6), as used to wrap organization keys. Like
+    /// the official clients, the mac of types 5 and 6 is ignored, as there is no key to verify
+    /// it with.
+    fn decrypt_rsa(&self, key: &RSAPrivateKey) -> Result<Vec<u8>, CipherError> {
+        let padding = match self.type_ {
+            EncryptionType::Rsa2048OaepSha256B64
+            | EncryptionType::Rsa2048OaepSha256HmacSha256B64 => PaddingScheme::new_oaep::<Sha256>(),
+            EncryptionType::Rsa2048OaepSha1B64 | EncryptionType::Rsa2048OaepSha1HmacSha256B64 => {
+                PaddingScheme::new_oaep::<Sha1>()
+            }
+            _ => return Err(CipherError::InvalidKeyType),
+        };
+
+        key.decrypt(padding, &self.ct).map_err(|_| CipherError::BlockModeError)
+    }
+}
+
+fn compute_mac(mac_key: &[u8], iv: &[u8], ct: &[u8]) -> Output<Hmac<Sha256>> {
+    let mut mac = Hmac::<Sha256>::new_varkey(mac_key).expect("hmac accepts keys of any length");
+    mac.update(iv);
+    mac.update(ct);
+    mac.finalize()
+}
+
+/// Encrypted values which can be decrypted as a whole using a single key, like the user or an
+/// organization key.
+pub trait Decrypt {
+    type Output;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output>;
+}
+
+impl Decrypt for CipherString {
+    type Output = SecretString;
+
+    fn decrypt(&self, key: &SymmetricKey) -> Option<SecretString> {
+        let plaintext = self.decrypt_raw(key).ok()?;
 
-        if !self.is_valid_mac(mac) {
-            return Err(CipherError::InvalidMac);
+        match String::from_utf8(plaintext) {
+            Ok(text) => Some(SecretString(text)),
+            Err(err) => {
+                err.into_bytes().zeroize();
+                None
+            }
         }
+    }
+}
 
-        // Currently only one cipher (type 2) is supported/used by bitwarden:
-        //   pbkdf2/aes-cbc-256/hmac-sha256
+/// Absent values decrypt successfully to `None`.
+impl<T: Decrypt> Decrypt for Option<T> {
+    type Output = Option<T::Output>;
 
-        Cbc::<Aes256, Pkcs7>::new_var(key, &self.iv)
-            .map_err(|_| CipherError::InvalidKeyLength)?
-            .decrypt_vec(&self.ct)
-            .map_err(|_| CipherError::BlockModeError)
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        match self {
+            Some(value) => value.decrypt(key).map(Some),
+            None => Some(None),
+        }
     }
+}
+
+impl<T: Decrypt> Decrypt for Vec<T> {
+    type Output = Vec<T::Output>;
 
-    pub fn decrypt(&self, cipher: &CipherSuite) -> Option<String> {
-        self.decrypt_raw(cipher.decrypt_key.as_ref()?, &cipher.mac_key)
-            .ok()
-            .and_then(|s| String::from_utf8(s).ok())
+    fn decrypt(&self, key: &SymmetricKey) -> Option<Self::Output> {
+        self.iter().map(|value| value.decrypt(key)).collect()
     }
 }
 
@@ -168,7 +721,7 @@ impl<'de> Visitor<'de> for CipherStringVisitor {
     }
 
     fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<CipherString, E> {
-        CipherString::from_str(value).ok_or_else(|| E::custom("invalid cipher string"))
+        value.parse().map_err(E::custom)
     }
 }
 
@@ -186,6 +739,6 @@ impl Serialize for CipherString {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&self.as_str())
+        serializer.collect_str(self)
     }
 }
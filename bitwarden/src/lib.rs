@@ -3,6 +3,7 @@
 pub mod api;
 pub mod api_definition;
 pub mod cipher;
+pub mod totp;
 
 pub use api::*;
 pub use api_definition::*;
@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: MIT
+
+//! Time-based one-time passwords (RFC 6238) as stored in the authenticator key of login items.
+
+use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac, NewMac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+
+use crate::cipher::SecretBytes;
+
+const STEAM_ALPHABET: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+const STEAM_DIGITS: usize = 5;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TotpError {
+    #[error("invalid totp secret")]
+    InvalidSecret,
+
+    #[error("invalid totp uri: {0}")]
+    InvalidUri(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+#[derive(Clone, Debug)]
+pub struct Totp {
+    secret: SecretBytes,
+    algorithm: Algorithm,
+    digits: usize,
+    period: u64,
+    steam: bool,
+}
+
+impl Totp {
+    /// Parses an authenticator key as accepted by the official clients: an `otpauth://totp/`
+    /// URI, a `steam://` secret for Steam Guard codes, or a bare base32 secret.
+    pub fn parse(key: &str) -> Result<Self, TotpError> {
+        let key = key.trim();
+
+        if key.starts_with("otpauth://") {
+            return Self::parse_uri(key);
+        }
+
+        if let Some(secret) = key.strip_prefix("steam://") {
+            return Ok(Self {
+                digits: STEAM_DIGITS,
+                steam: true,
+                ..Self::from_secret(secret)?
+            });
+        }
+
+        Self::from_secret(key)
+    }
+
+    fn from_secret(secret: &str) -> Result<Self, TotpError> {
+        let secret = decode_base32(secret).ok_or(TotpError::InvalidSecret)?;
+
+        if secret.is_empty() {
+            return Err(TotpError::InvalidSecret);
+        }
+
+        Ok(Self {
+            secret: secret.into(),
+            algorithm: Algorithm::Sha1,
+            digits: 6,
+            period: 30,
+            steam: false,
+        })
+    }
+
+    fn parse_uri(uri: &str) -> Result<Self, TotpError> {
+        let url = Url::parse(uri).map_err(|err| TotpError::InvalidUri(err.to_string()))?;
+
+        if url.host_str() != Some("totp") {
+            return Err(TotpError::InvalidUri("only totp is supported".to_owned()));
+        }
+
+        let secret = url
+            .query_pairs()
+            .find(|(key, _)| key == "secret")
+            .ok_or_else(|| TotpError::InvalidUri("missing secret".to_owned()))?
+            .1;
+
+        let mut totp = Self::from_secret(&secret)?;
+
+        for (key, value) in url.query_pairs() {
+            let invalid = || TotpError::InvalidUri(format!("invalid {}: {}", key, value));
+
+            match key.as_ref() {
+                "algorithm" => {
+                    totp.algorithm = match value.to_ascii_uppercase().as_str() {
+                        "SHA1" => Algorithm::Sha1,
+                        "SHA256" => Algorithm::Sha256,
+                        "SHA512" => Algorithm::Sha512,
+                        _ => return Err(invalid()),
+                    }
+                }
+                "digits" => {
+                    totp.digits = value.parse().map_err(|_| invalid())?;
+
+                    if !(1..=10).contains(&totp.digits) {
+                        return Err(invalid());
+                    }
+                }
+                "period" => {
+                    totp.period = value.parse().map_err(|_| invalid())?;
+
+                    if totp.period == 0 {
+                        return Err(invalid());
+                    }
+                }
+                "encoder" if value == "steam" => {
+                    totp.digits = STEAM_DIGITS;
+                    totp.steam = true;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(totp)
+    }
+
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// Generates the code valid at the given unix timestamp.
+    pub fn generate(&self, time: u64) -> String {
+        let counter = (time / self.period).to_be_bytes();
+
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, &counter),
+        };
+
+        // Dynamic truncation, see RFC 4226 section 5.3
+        let offset = (hash[hash.len() - 1] & 0xf) as usize;
+        let bytes: [u8; 4] = hash[offset..offset + 4].try_into().unwrap();
+        let mut code = u32::from_be_bytes(bytes) & 0x7fff_ffff;
+
+        if self.steam {
+            (0..self.digits)
+                .map(|_| {
+                    let c = STEAM_ALPHABET[code as usize % STEAM_ALPHABET.len()];
+                    code /= STEAM_ALPHABET.len() as u32;
+                    c as char
+                })
+                .collect()
+        } else {
+            let code = code as u64 % 10u64.pow(self.digits as u32);
+            format!("{:0width$}", code, width = self.digits)
+        }
+    }
+
+    /// Returns the current code and the number of seconds it stays valid.
+    pub fn now(&self) -> (String, u64) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        (self.generate(time), self.period - time % self.period)
+    }
+}
+
+fn hmac<M: Mac + NewMac>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = M::new_varkey(key).unwrap();
+    mac.update(message);
+
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Decodes RFC 4648 base32, ignoring case, whitespace and padding.
+fn decode_base32(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in input.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u32 - 'A' as u32,
+            c @ '2'..='7' => c as u32 - '2' as u32 + 26,
+            _ => return None,
+        };
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Some(output)
+}
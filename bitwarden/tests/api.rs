@@ -0,0 +1,738 @@
+// SPDX-License-Identifier: MIT
+
+mod common;
+
+use bitwarden::cipher::{CipherError, CipherString, CipherSuite, Decrypt, Kdf, SymmetricKey};
+use bitwarden::{
+    ApiError, ApiResult, AuthData, CipherEntry, CipherType, Device, ItemData, ItemEdit,
+    SecureNote, ServerConfig, SyncResponse, TwoFactorProvider, TwoFactorToken,
+};
+use serde_json::Value;
+use uuid::Uuid;
+
+use common::MockServer;
+
+const EMAIL: &str = "user@example.com";
+
+const PRELOGIN: &str = include_str!("fixtures/prelogin.json");
+const TOKEN: &str = include_str!("fixtures/token.json");
+const EXPIRED_TOKEN: &str = include_str!("fixtures/token_expired.json");
+const TWO_FACTOR_CHALLENGE: &str = include_str!("fixtures/two_factor_challenge.json");
+const SYNC: &str = include_str!("fixtures/sync.json");
+const SYNC_ORGANIZATION: &str = include_str!("fixtures/sync_organization.json");
+const SYNC_CURRENT: &str = include_str!("fixtures/sync_current.json");
+const SYNC_LEGACY: &str = include_str!("fixtures/sync_legacy.json");
+const SYNC_ITEMS: &str = include_str!("fixtures/sync_items.json");
+const CIPHER: &str = include_str!("fixtures/cipher.json");
+const CIPHER_CHANGED: &str = include_str!("fixtures/cipher_changed.json");
+
+const CIPHER_PATH: &str = "/api/ciphers/1e2d3c4b-5a69-4788-96a5-b4c3d2e1f001";
+
+fn login(
+    server: &ServerConfig,
+    device: &mut Device,
+    two_factor: Option<&TwoFactorToken>,
+) -> ApiResult<AuthData> {
+    bitwarden::authenticate(server, device, EMAIL, "password", two_factor)
+}
+
+/// Syncs the given response and unlocks it with the user key.
+fn sync_unlocked(response: &str) -> (AuthData, SyncResponse) {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, TOKEN)
+        .route("GET", "/api/sync", 200, response);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    let sync = bitwarden::sync(&mut auth).unwrap();
+
+    auth.cipher.set_decrypt_key(&sync.profile.key).unwrap();
+
+    (auth, sync)
+}
+
+/// Decrypts an encrypted value of a request body.
+fn decrypt_value(value: &Value, key: &SymmetricKey) -> String {
+    let value: CipherString = serde_json::from_value(value.clone()).unwrap();
+    value.decrypt(key).unwrap().to_string()
+}
+
+fn login_edit(password: &str) -> ItemEdit {
+    ItemEdit {
+        name: "Renamed".to_owned(),
+        notes: "".into(),
+        favorite: false,
+        username: "alice".to_owned(),
+        password: password.into(),
+        uris: vec!["https://example.com".to_owned(), "https://example.org".to_owned()],
+    }
+}
+
+#[test]
+fn default_server_is_bitwarden_cloud() {
+    let server = ServerConfig::default();
+
+    assert_eq!(server.identity_url, "https://identity.bitwarden.com");
+    assert_eq!(server.api_url, "https://api.bitwarden.com");
+    assert_eq!(server.base_url(), None);
+}
+
+#[test]
+fn base_url_derives_both_endpoints() {
+    let server = ServerConfig::from_base_url("https://vault.example.com/");
+
+    assert_eq!(server.identity_url, "https://vault.example.com/identity");
+    assert_eq!(server.api_url, "https://vault.example.com/api");
+    assert_eq!(server.base_url(), Some("https://vault.example.com"));
+}
+
+#[test]
+fn authenticate_and_sync_with_separate_endpoints() {
+    let identity = MockServer::start();
+    identity.route("POST", "/connect/token", 200, TOKEN);
+
+    let api = MockServer::start();
+    api.route("POST", "/accounts/prelogin", 200, PRELOGIN)
+        .route("GET", "/sync", 200, SYNC);
+
+    let server = ServerConfig {
+        identity_url: identity.url().to_owned(),
+        api_url: api.url().to_owned(),
+    };
+
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    assert_eq!(auth.server, server);
+
+    let sync = bitwarden::sync(&mut auth).unwrap();
+    assert_eq!(sync.profile.email, EMAIL);
+
+    let token_request = identity.request_to("/connect/token").unwrap();
+    assert!(token_request.body.contains("grant_type=password"));
+    assert!(token_request.body.contains("username=user%40example.com"));
+
+    let sync_request = api.request_to("/sync").unwrap();
+    assert_eq!(sync_request.header("authorization"), Some("Bearer mock-access-token"));
+}
+
+#[test]
+fn authenticate_and_sync_with_base_url() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, TOKEN)
+        .route("GET", "/api/sync", 200, SYNC);
+
+    let server = ServerConfig::from_base_url(mock.url());
+
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    assert!(!auth.is_expired());
+
+    bitwarden::sync(&mut auth).unwrap();
+
+    let paths: Vec<_> = mock.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, ["/api/accounts/prelogin", "/identity/connect/token", "/api/sync"]);
+}
+
+#[test]
+fn organization_items_decrypt_with_organization_key() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, TOKEN)
+        .route("GET", "/api/sync", 200, SYNC_ORGANIZATION);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    let sync = bitwarden::sync(&mut auth).unwrap();
+
+    auth.cipher.set_decrypt_key(&sync.profile.key).unwrap();
+    sync.profile.unlock_organizations(&mut auth.cipher).unwrap();
+
+    let organization = &sync.profile.organizations[0];
+    let invited = &sync.profile.organizations[1];
+    assert!(auth.cipher.key_for(Some(organization.uuid)).is_some());
+    assert!(auth.cipher.key_for(Some(invited.uuid)).is_none());
+
+    let item = &sync.ciphers[0];
+    let key = item.key(&auth.cipher).unwrap();
+    assert_eq!(item.name.decrypt(&key).unwrap(), "Shared note");
+    assert_eq!(item.notes.decrypt(&key).unwrap().unwrap(), "shared secret");
+
+    // Organization items cannot be decrypted with the user key
+    assert!(item.name.decrypt(auth.cipher.key_for(None).unwrap()).is_none());
+
+    let collection = &sync.collections[0];
+    assert_eq!(collection.organization_id, organization.uuid);
+    assert_eq!(collection.name.decrypt(&key).unwrap(), "Engineering");
+}
+
+#[test]
+fn broken_organization_key_only_skips_its_organization() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, TOKEN)
+        .route("GET", "/api/sync", 200, SYNC_ORGANIZATION);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    let mut sync = bitwarden::sync(&mut auth).unwrap();
+
+    // Wrapped for another private key, so it fails to unwrap
+    let mut broken = sync.profile.organizations[0].clone();
+    broken.uuid = Uuid::from_u128(1);
+    broken.key = Some(format!("4.{}", base64::encode([0u8; 256])).parse().unwrap());
+    sync.profile.organizations.insert(0, broken);
+
+    auth.cipher.set_decrypt_key(&sync.profile.key).unwrap();
+    let skipped = sync.profile.unlock_organizations(&mut auth.cipher).unwrap();
+
+    assert_eq!(skipped, [Uuid::from_u128(1)]);
+    assert!(auth.cipher.key_for(Some(Uuid::from_u128(1))).is_none());
+
+    let item = &sync.ciphers[0];
+    let key = item.key(&auth.cipher).unwrap();
+    assert_eq!(item.name.decrypt(&key).unwrap(), "Shared note");
+}
+
+#[test]
+fn current_sync_schema() {
+    let (auth, sync) = sync_unlocked(SYNC_CURRENT);
+    let key = auth.cipher.key_for(None).unwrap();
+
+    assert_eq!(sync.profile.email, EMAIL);
+    assert_eq!(sync.profile.name, None);
+    assert_eq!(sync.folders[0].name.decrypt(key).unwrap(), "Work");
+
+    let item = &sync.ciphers[0];
+    assert_eq!(item.folder_id, Some(sync.folders[0].uuid));
+    assert!(item.favorite);
+    assert_eq!(item.name.decrypt(key).unwrap(), "Example");
+
+    let login = match item.decrypt_data(key).unwrap() {
+        ItemData::Login(login) => login,
+        other => panic!("expected login, got {:?}", other),
+    };
+
+    assert_eq!(login.username.unwrap(), "alice");
+    assert_eq!(login.password.unwrap(), "hunter2");
+    assert_eq!(login.totp.unwrap(), "JBSWY3DPEHPK3PXP");
+    assert_eq!(login.uris.unwrap()[0].uri.as_ref().unwrap(), "https://example.com");
+
+    let fields = item.fields.decrypt(key).unwrap().unwrap();
+    assert!(fields[0].is_hidden());
+    assert_eq!(fields[0].value.as_ref().unwrap(), "1234");
+
+    let history = item.password_history.decrypt(key).unwrap().unwrap();
+    assert_eq!(history[0].password, "hunter1");
+
+    match sync.ciphers[1].decrypt_data(key).unwrap() {
+        ItemData::Card(card) => assert_eq!(card.number.unwrap(), "4111111111111111"),
+        other => panic!("expected card, got {:?}", other),
+    }
+}
+
+#[test]
+fn card_item_data() {
+    let (auth, sync) = sync_unlocked(SYNC_ITEMS);
+    let key = auth.cipher.key_for(None).unwrap();
+
+    let item = &sync.ciphers[0];
+    assert_eq!(item.name.decrypt(key).unwrap(), "Visa");
+
+    let card = match item.decrypt_data(key).unwrap() {
+        ItemData::Card(card) => card,
+        other => panic!("expected card, got {:?}", other),
+    };
+
+    assert_eq!(card.cardholder_name.unwrap(), "Alice Example");
+    assert_eq!(card.brand.unwrap(), "Visa");
+    assert_eq!(card.number.unwrap(), "4111111111111111");
+    assert_eq!(card.exp_month.unwrap(), "12");
+    assert_eq!(card.exp_year.unwrap(), "2030");
+    assert_eq!(card.code.unwrap(), "123");
+}
+
+#[test]
+fn identity_item_data() {
+    let (auth, sync) = sync_unlocked(SYNC_ITEMS);
+    let key = auth.cipher.key_for(None).unwrap();
+
+    let item = &sync.ciphers[1];
+    assert_eq!(item.name.decrypt(key).unwrap(), "Passport");
+
+    let identity = match item.decrypt_data(key).unwrap() {
+        ItemData::Identity(identity) => identity,
+        other => panic!("expected identity, got {:?}", other),
+    };
+
+    assert_eq!(identity.title.unwrap(), "Ms");
+    assert_eq!(identity.first_name.unwrap(), "Alice");
+    assert!(identity.middle_name.is_none());
+    assert_eq!(identity.last_name.unwrap(), "Example");
+    assert_eq!(identity.address1.unwrap(), "1 Main Street");
+    assert_eq!(identity.city.unwrap(), "Springfield");
+    assert_eq!(identity.postal_code.unwrap(), "12345");
+    assert_eq!(identity.country.unwrap(), "US");
+    assert_eq!(identity.company.unwrap(), "Example Inc.");
+    assert_eq!(identity.email.unwrap(), "alice@example.com");
+    assert_eq!(identity.phone.unwrap(), "+1 555 0100");
+    assert!(identity.ssn.is_none());
+    assert_eq!(identity.username.unwrap(), "alice");
+    assert_eq!(identity.passport_number.unwrap(), "X1234567");
+    assert!(identity.license_number.is_none());
+}
+
+#[test]
+fn secure_note_item_data() {
+    let (auth, sync) = sync_unlocked(SYNC_ITEMS);
+    let key = auth.cipher.key_for(None).unwrap();
+
+    let item = &sync.ciphers[2];
+    assert_eq!(item.name.decrypt(key).unwrap(), "Recovery codes");
+    assert_eq!(item.notes.decrypt(key).unwrap().unwrap(), "1111-2222\n3333-4444");
+
+    match item.decrypt_data(key).unwrap() {
+        ItemData::SecureNote => {}
+        other => panic!("expected secure note, got {:?}", other),
+    }
+}
+
+#[test]
+fn item_key_is_unwrapped_with_user_key() {
+    let (auth, sync) = sync_unlocked(SYNC_CURRENT);
+
+    let item = &sync.ciphers[2];
+    assert!(item.item_key.is_some());
+
+    let key = item.key(&auth.cipher).unwrap();
+    assert_eq!(item.name.decrypt(&key).unwrap(), "Item key");
+
+    match item.decrypt_data(&key).unwrap() {
+        ItemData::Login(login) => {
+            assert_eq!(login.username.unwrap(), "carol");
+            assert_eq!(login.password.unwrap(), "item secret");
+        }
+        other => panic!("expected login, got {:?}", other),
+    }
+
+    // The item's own key is required, the user key only wraps it
+    assert!(item.name.decrypt(auth.cipher.key_for(None).unwrap()).is_none());
+}
+
+#[test]
+fn values_are_encrypted_with_the_item_key() {
+    let (auth, sync) = sync_unlocked(SYNC_CURRENT);
+
+    for item in &sync.ciphers {
+        let key = item.key(&auth.cipher).unwrap();
+        let encrypted = auth.cipher.encrypt_for(item, b"secret").unwrap();
+        assert_eq!(encrypted.decrypt(&key).unwrap(), "secret");
+    }
+
+    // Items with their own key are not encrypted with the user key
+    let encrypted = auth.cipher.encrypt_for(&sync.ciphers[2], b"secret").unwrap();
+    assert!(encrypted.decrypt(auth.cipher.key_for(None).unwrap()).is_none());
+
+    let locked = CipherSuite::default();
+    assert!(matches!(locked.encrypt_for(&sync.ciphers[0], b"secret"), Err(CipherError::Locked)));
+}
+
+#[test]
+fn legacy_sync_schema_with_data_blob() {
+    let (auth, sync) = sync_unlocked(SYNC_LEGACY);
+    let key = auth.cipher.key_for(None).unwrap();
+
+    let item = &sync.ciphers[0];
+    assert!(item.login.is_none());
+    assert_eq!(item.name.decrypt(key).unwrap(), "Legacy login");
+    assert_eq!(item.notes.decrypt(key).unwrap().unwrap(), "legacy notes");
+
+    let login = match item.decrypt_data(key).unwrap() {
+        ItemData::Login(login) => login,
+        other => panic!("expected login, got {:?}", other),
+    };
+
+    assert_eq!(login.username.unwrap(), "bob");
+    assert_eq!(login.password.unwrap(), "correct horse");
+    assert_eq!(login.totp.unwrap(), "JBSWY3DPEHPK3PXP");
+    assert_eq!(login.uris.unwrap()[0].uri.as_ref().unwrap(), "https://example.com");
+}
+
+#[test]
+fn argon2id_prelogin() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, include_str!("fixtures/prelogin_argon2.json"))
+        .route("POST", "/identity/connect/token", 200, TOKEN);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let auth = login(&server, &mut Device::default(), None).unwrap();
+
+    let expected = Kdf::Argon2id {
+        iterations: 3,
+        memory: 16,
+        parallelism: 2,
+    };
+
+    assert_eq!(auth.kdf, expected);
+}
+
+#[test]
+fn rejected_login_is_reported() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 400, r#"{"error":"invalid_grant"}"#);
+
+    let server = ServerConfig::from_base_url(mock.url());
+
+    assert!(matches!(
+        login(&server, &mut Device::default(), None),
+        Err(ApiError::LoginFailed),
+    ));
+}
+
+#[test]
+fn unreachable_server_is_a_network_error() {
+    // Nothing listens on the discard port
+    let server = ServerConfig::from_base_url("http://127.0.0.1:9");
+
+    assert!(matches!(
+        login(&server, &mut Device::default(), None),
+        Err(ApiError::Network(_)),
+    ));
+}
+
+#[test]
+fn failed_sync_reports_status_and_invalid_responses() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, TOKEN)
+        .route("GET", "/api/sync", 500, "{}");
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+
+    match bitwarden::sync(&mut auth) {
+        Err(ApiError::Status { status, .. }) => assert_eq!(status.as_u16(), 500),
+        other => panic!("expected status error, got {:?}", other),
+    }
+
+    mock.route("GET", "/api/sync", 200, r#"{"profile":null}"#);
+    assert!(matches!(bitwarden::sync(&mut auth), Err(ApiError::Parse { .. })));
+
+    mock.route("GET", "/api/sync", 401, "{}");
+    assert!(matches!(bitwarden::sync(&mut auth), Err(ApiError::SessionExpired)));
+}
+
+#[test]
+fn sync_refreshes_expired_token() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, EXPIRED_TOKEN)
+        .route("GET", "/api/sync", 200, SYNC);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    assert!(auth.is_expired());
+
+    mock.route("POST", "/identity/connect/token", 200, TOKEN);
+    bitwarden::sync(&mut auth).unwrap();
+    assert!(!auth.is_expired());
+
+    let token_requests: Vec<_> = mock
+        .requests()
+        .into_iter()
+        .filter(|r| r.path == "/identity/connect/token")
+        .collect();
+
+    assert_eq!(token_requests.len(), 2);
+    assert!(token_requests[1].body.contains("grant_type=refresh_token"));
+    assert!(token_requests[1].body.contains("refresh_token=expiring-refresh-token"));
+    assert!(!token_requests[1].body.contains("password"));
+
+    let sync_request = mock.request_to("/api/sync").unwrap();
+    assert_eq!(sync_request.header("authorization"), Some("Bearer mock-access-token"));
+}
+
+#[test]
+fn expired_session_without_refresh_token() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route(
+            "POST",
+            "/identity/connect/token",
+            200,
+            r#"{"access_token":"a","expires_in":0,"token_type":"Bearer"}"#,
+        );
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+
+    assert!(matches!(bitwarden::sync(&mut auth), Err(ApiError::SessionExpired)));
+    assert!(mock.request_to("/api/sync").is_none());
+}
+
+#[test]
+fn rejected_refresh_token_ends_session() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 200, EXPIRED_TOKEN);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut auth = login(&server, &mut Device::default(), None).unwrap();
+    assert!(auth.has_session());
+
+    mock.route("POST", "/identity/connect/token", 400, r#"{"error":"invalid_grant"}"#);
+
+    assert!(matches!(bitwarden::sync(&mut auth), Err(ApiError::SessionExpired)));
+    assert!(!auth.has_session());
+    assert!(mock.request_to("/api/sync").is_none());
+}
+
+#[test]
+fn two_factor_challenge_lists_providers() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 400, TWO_FACTOR_CHALLENGE);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let result = login(&server, &mut Device::default(), None);
+
+    let expected = [
+        TwoFactorProvider::Authenticator,
+        TwoFactorProvider::Email,
+        TwoFactorProvider::WebAuthn,
+    ];
+
+    match result {
+        Err(ApiError::TwoFactorRequired(providers)) => assert_eq!(providers, expected),
+        other => panic!("expected two-factor challenge, got {:?}", other),
+    }
+}
+
+#[test]
+fn two_factor_login_remembers_device() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route(
+            "POST",
+            "/identity/connect/token",
+            200,
+            include_str!("fixtures/token_remember.json"),
+        );
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut device = Device::default();
+    let two_factor = TwoFactorToken {
+        provider: TwoFactorProvider::Authenticator,
+        token: "123456".to_owned(),
+        remember: true,
+    };
+
+    login(&server, &mut device, Some(&two_factor)).unwrap();
+    assert_eq!(device.remember_token.as_deref(), Some("remember-me"));
+
+    let request = mock.request_to("/identity/connect/token").unwrap();
+    assert!(request.body.contains("twoFactorToken=123456"));
+    assert!(request.body.contains("twoFactorProvider=0"));
+    assert!(request.body.contains("twoFactorRemember=1"));
+    assert!(request.body.contains(&format!("deviceIdentifier={}", device.identifier)));
+
+    // The next login presents the remembered token instead of asking for a code
+    login(&server, &mut device, None).unwrap();
+
+    let request = mock
+        .requests()
+        .into_iter()
+        .rev()
+        .find(|r| r.path == "/identity/connect/token")
+        .unwrap();
+    assert!(request.body.contains("twoFactorToken=remember-me"));
+    assert!(request.body.contains("twoFactorProvider=5"));
+}
+
+#[test]
+fn rejected_remember_token_is_dropped() {
+    let mock = MockServer::start();
+    mock.route("POST", "/api/accounts/prelogin", 200, PRELOGIN)
+        .route("POST", "/identity/connect/token", 400, TWO_FACTOR_CHALLENGE);
+
+    let server = ServerConfig::from_base_url(mock.url());
+    let mut device = Device {
+        remember_token: Some("stale".to_owned()),
+        ..Device::default()
+    };
+
+    let result = login(&server, &mut device, None);
+
+    assert!(matches!(result, Err(ApiError::TwoFactorRequired(_))));
+    assert_eq!(device.remember_token, None);
+}
+
+#[test]
+fn edit_keeps_other_values_and_records_password_history() {
+    let (auth, sync) = sync_unlocked(SYNC_CURRENT);
+    let mut item = sync.ciphers[0].clone();
+    let key = item.key(&auth.cipher).unwrap().into_owned();
+
+    item.apply_edit(&login_edit("hunter3"), &auth.cipher).unwrap();
+
+    assert_eq!(item.name.decrypt(&key).unwrap(), "Renamed");
+    assert!(item.notes.is_none());
+    assert!(!item.favorite);
+
+    let login = item.login.as_ref().unwrap();
+    let uris = login.uris.as_ref().unwrap();
+    assert_eq!(login.username.decrypt(&key).unwrap().unwrap(), "alice");
+    assert_eq!(login.password.decrypt(&key).unwrap().unwrap(), "hunter3");
+    assert_eq!(login.totp.decrypt(&key).unwrap().unwrap(), "JBSWY3DPEHPK3PXP");
+    assert!(login.password_last_changed.is_some());
+    assert!(login.other.contains_key("fido2Credentials"));
+
+    // The unchanged URI is kept as is, including its checksum
+    let old_uri = &sync.ciphers[0].login.as_ref().unwrap().uris.as_ref().unwrap()[0];
+    let (uri, old_uri) = (uris[0].uri.as_ref().unwrap(), old_uri.uri.as_ref().unwrap());
+    assert_eq!(uri.to_string(), old_uri.to_string());
+    assert!(uris[0].other.contains_key("uriChecksum"));
+    assert_eq!(uris[1].uri.decrypt(&key).unwrap().unwrap(), "https://example.org");
+
+    let history = item.password_history.decrypt(&key).unwrap().unwrap();
+    let history: Vec<_> = history.iter().map(|old| old.password.to_string()).collect();
+    assert_eq!(history, ["hunter2", "hunter1"]);
+
+    let fields = item.fields.decrypt(&key).unwrap().unwrap();
+    assert_eq!(fields[0].value.as_ref().unwrap(), "1234");
+
+    // Saving the same password again does not add it to the history
+    item.apply_edit(&login_edit("hunter3"), &auth.cipher).unwrap();
+    assert_eq!(item.password_history.unwrap().len(), 2);
+}
+
+#[test]
+fn update_sends_changes_along_with_revision_date() {
+    let (mut auth, sync) = sync_unlocked(SYNC_CURRENT);
+    let mut item = sync.ciphers[0].clone();
+    let key = item.key(&auth.cipher).unwrap().into_owned();
+    item.apply_edit(&login_edit("hunter3"), &auth.cipher).unwrap();
+
+    let mock = MockServer::start();
+    mock.route("GET", CIPHER_PATH, 200, CIPHER)
+        .route("PUT", CIPHER_PATH, 200, CIPHER_CHANGED);
+    auth.server = ServerConfig::from_base_url(mock.url());
+
+    let updated = bitwarden::update_cipher(&mut auth, &item).unwrap();
+    assert!(updated.last_changed > item.last_changed);
+
+    let request = mock.requests().into_iter().find(|r| r.method == "PUT").unwrap();
+    assert_eq!(request.header("authorization"), Some("Bearer mock-access-token"));
+
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["lastKnownRevisionDate"], "2023-04-05T06:07:08Z");
+    assert_eq!(body["type"], 1);
+    assert_eq!(decrypt_value(&body["name"], &key), "Renamed");
+    assert_eq!(decrypt_value(&body["login"]["password"], &key), "hunter3");
+    assert_eq!(decrypt_value(&body["passwordHistory"][0]["password"], &key), "hunter2");
+
+    // Values unknown to this client are sent back unchanged
+    assert_eq!(body["reprompt"], 0);
+    assert_eq!(body["login"]["fido2Credentials"], Value::Array(Vec::new()));
+    assert_eq!(body["fields"][0]["type"], 1);
+    assert!(body["fields"][0]["linkedId"].is_null());
+    assert!(body["login"]["uris"][0]["uriChecksum"].is_null());
+
+    // Only values the server accepts are sent, not the ones it only reports
+    let body = body.as_object().unwrap();
+    for key in ["attachments", "creationDate", "deletedDate", "revisionDate", "object", "edit"] {
+        assert!(!body.contains_key(key), "{} was sent", key);
+    }
+}
+
+#[test]
+fn update_of_legacy_item_sends_only_its_type_data() {
+    let (mut auth, sync) = sync_unlocked(SYNC_LEGACY);
+
+    // Turn the login into a note, as synced by older servers with the values in the data blob
+    let mut item = sync.ciphers[0].clone();
+    item.type_ = CipherType::SecureNote as usize;
+    item.secure_note = Some(SecureNote { type_: 0 });
+    assert!(item.data.is_some());
+
+    item.apply_edit(&login_edit("hunter3"), &auth.cipher).unwrap();
+
+    let mock = MockServer::start();
+    mock.route("GET", CIPHER_PATH, 200, CIPHER)
+        .route("PUT", CIPHER_PATH, 200, CIPHER_CHANGED);
+    auth.server = ServerConfig::from_base_url(mock.url());
+    item.uuid = CIPHER_PATH.rsplit('/').next().unwrap().parse().unwrap();
+    item.last_changed = chrono::Utc::now();
+
+    bitwarden::update_cipher(&mut auth, &item).unwrap();
+
+    let request = mock.requests().into_iter().find(|r| r.method == "PUT").unwrap();
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["type"], 2);
+    assert_eq!(body["secureNote"]["type"], 0);
+    assert!(body["login"].is_null());
+}
+
+#[test]
+fn update_does_not_overwrite_newer_changes() {
+    let (mut auth, sync) = sync_unlocked(SYNC_CURRENT);
+
+    let mock = MockServer::start();
+    mock.route("GET", CIPHER_PATH, 200, CIPHER_CHANGED)
+        .route("PUT", CIPHER_PATH, 200, CIPHER_CHANGED);
+    auth.server = ServerConfig::from_base_url(mock.url());
+
+    assert!(matches!(
+        bitwarden::update_cipher(&mut auth, &sync.ciphers[0]),
+        Err(ApiError::OutOfDate),
+    ));
+    assert!(mock.requests().iter().all(|r| r.method != "PUT"));
+
+    // The server might refuse the change on its own as well
+    mock.route("GET", CIPHER_PATH, 200, CIPHER)
+        .route("PUT", CIPHER_PATH, 400, r#"{"message":"The cipher is out of date."}"#);
+
+    match bitwarden::update_cipher(&mut auth, &sync.ciphers[0]) {
+        Err(ApiError::Rejected(message)) => assert_eq!(message, "The cipher is out of date."),
+        other => panic!("expected rejected change, got {:?}", other),
+    }
+}
+
+#[test]
+fn create_personal_and_organization_items() {
+    let (mut auth, sync) = sync_unlocked(SYNC_ORGANIZATION);
+    sync.profile.unlock_organizations(&mut auth.cipher).unwrap();
+
+    let mock = MockServer::start();
+    mock.route("POST", "/api/ciphers", 200, CIPHER)
+        .route("POST", "/api/ciphers/create", 200, CIPHER);
+    auth.server = ServerConfig::from_base_url(mock.url());
+
+    let user_key = auth.cipher.key_for(None).unwrap().clone();
+    let item = CipherEntry::new_login(&login_edit("secret"), None, &auth.cipher).unwrap();
+    let created = bitwarden::create_cipher(&mut auth, &item).unwrap();
+    assert_eq!(created.uuid.to_string(), "1e2d3c4b-5a69-4788-96a5-b4c3d2e1f001");
+
+    let request = mock.request_to("/api/ciphers").unwrap();
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert!(body["organizationId"].is_null());
+    assert!(body["lastKnownRevisionDate"].is_null());
+    assert_eq!(decrypt_value(&body["login"]["password"], &user_key), "secret");
+    assert!(body["passwordHistory"].is_null());
+
+    let collection = &sync.collections[0];
+    let organization_key = auth.cipher.key_for(Some(collection.organization_id)).unwrap().clone();
+    let organization = Some(collection.organization_id);
+    let mut item =
+        CipherEntry::new_login(&login_edit("shared"), organization, &auth.cipher).unwrap();
+    item.collection_ids = vec![collection.uuid];
+
+    let created = bitwarden::create_cipher(&mut auth, &item).unwrap();
+    assert_eq!(created.collection_ids, [collection.uuid]);
+
+    let request = mock.request_to("/api/ciphers/create").unwrap();
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["collectionIds"][0], collection.uuid.to_string());
+    assert_eq!(body["cipher"]["organizationId"], collection.organization_id.to_string());
+    assert_eq!(decrypt_value(&body["cipher"]["name"], &organization_key), "Renamed");
+}
@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: MIT
+
+use bitwarden::cipher::{
+    CipherError, CipherString, CipherSuite, Decrypt, EncryptionType, Kdf, SecretString,
+    SymmetricKey,
+};
+use uuid::Uuid;
+
+const EMAIL: &str = "user@example.com";
+const PASSWORD: &str = "correct horse battery staple";
+
+/// Private key encrypted with a user key of `[9; 64]`, followed by organization keys of `[n; 64]`
+/// wrapped using encryption type n, for n in 3 to 6.
+const RSA_KEYS: &str = include_str!("fixtures/rsa_keys.txt");
+
+/// User key along with values encrypted with it by this crate, each as cipher string and
+/// plaintext. Decrypting them guards against changes in the format or the decryption.
+const CIPHER_STRINGS: &str = include_str!("fixtures/cipher_strings.json");
+
+/// Values encrypted by the official clients, taken from the test suites of the Bitwarden SDK and
+/// the web client, each with its source, key and plaintext.
+const OFFICIAL_CIPHER_STRINGS: &str = include_str!("fixtures/official_cipher_strings.json");
+
+fn user_key_cipher(key: &str) -> CipherSuite {
+    let key = base64::decode(key).unwrap();
+    let mut cipher = CipherSuite::default();
+    cipher.set_user_key(SymmetricKey::from_bytes(&key).unwrap());
+    cipher
+}
+
+fn parse(text: &str) -> CipherString {
+    text.parse().unwrap()
+}
+
+#[test]
+fn pbkdf2_master_key_hash() {
+    let cipher = CipherSuite::from(EMAIL, PASSWORD, Kdf::Pbkdf2 { iterations: 5000 }).unwrap();
+
+    assert_eq!(cipher.master_key_hash, "0FMeontUyfpu9Ga/DvERL9LMAXg9KB82VK6UqHdnKko=");
+}
+
+#[test]
+fn argon2id_master_key_hash() {
+    let kdf = Kdf::Argon2id {
+        iterations: 3,
+        memory: 16,
+        parallelism: 2,
+    };
+
+    let cipher = CipherSuite::from(EMAIL, PASSWORD, kdf).unwrap();
+
+    assert_eq!(cipher.master_key_hash, "jNMqK+ig2sLnM+4bwVDg2oeYRAodWoFPf9dhumMlRMg=");
+}
+
+#[test]
+fn unsupported_argon2id_parameters_are_an_error() {
+    for (memory, parallelism) in [(0, 4), (16, 0)] {
+        let kdf = Kdf::Argon2id {
+            iterations: 3,
+            memory,
+            parallelism,
+        };
+
+        let result = CipherSuite::from(EMAIL, PASSWORD, kdf);
+        assert!(matches!(result, Err(CipherError::UnsupportedKdf(_))), "{:?}", kdf);
+    }
+}
+
+#[test]
+fn debug_output_redacts_secrets() {
+    let cipher = CipherSuite::from(EMAIL, PASSWORD, Kdf::Pbkdf2 { iterations: 5000 }).unwrap();
+    let debug = format!("{:?}", cipher);
+
+    assert!(!debug.contains(&*cipher.master_key_hash));
+
+    let secret = SecretString::from(PASSWORD);
+    assert_eq!(format!("{:?}", secret), "SecretString(<redacted>)");
+    assert_eq!(&*secret, PASSWORD);
+}
+
+#[test]
+fn encrypt_roundtrip() {
+    let key = SymmetricKey::from_bytes(&[7u8; 64]).unwrap();
+    let first = CipherString::encrypt(PASSWORD.as_bytes(), &key);
+    let second = CipherString::encrypt(PASSWORD.as_bytes(), &key);
+
+    assert_eq!(first.decrypt(&key).unwrap(), PASSWORD);
+    assert_eq!(second.decrypt(&key).unwrap(), PASSWORD);
+
+    // Every encryption uses a fresh IV
+    assert_ne!(format!("{:?}", first), format!("{:?}", second));
+}
+
+#[test]
+fn encrypted_values_round_trip() {
+    let fixture: serde_json::Value = serde_json::from_str(CIPHER_STRINGS).unwrap();
+    let cipher = user_key_cipher(fixture["userKey"].as_str().unwrap());
+    let key = cipher.key_for(None).unwrap();
+
+    for value in fixture["values"].as_array().unwrap() {
+        let text = value["encrypted"].as_str().unwrap();
+        let plaintext = value["plaintext"].as_str().unwrap();
+
+        assert_eq!(parse(text).decrypt_raw(key).unwrap(), plaintext.as_bytes());
+
+        // Formatted like the values of the official clients: IV, ciphertext and mac
+        let encrypted = cipher.encrypt(plaintext.as_bytes()).unwrap().to_string();
+        let parts: Vec<_> = encrypted.strip_prefix("2.").unwrap().split('|').collect();
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(base64::decode(parts[0]).unwrap().len(), 16);
+        assert_eq!(base64::decode(parts[2]).unwrap().len(), 32);
+        assert_eq!(parse(&encrypted).decrypt_raw(key).unwrap(), plaintext.as_bytes());
+        assert_eq!(parse(text).to_string(), text);
+    }
+}
+
+#[test]
+fn values_of_official_clients_decrypt() {
+    let fixtures: serde_json::Value = serde_json::from_str(OFFICIAL_CIPHER_STRINGS).unwrap();
+
+    for fixture in fixtures.as_array().unwrap() {
+        let source = fixture["source"].as_str().unwrap();
+        let key = base64::decode(fixture["key"].as_str().unwrap()).unwrap();
+        let key = SymmetricKey::from_bytes(&key).unwrap();
+        let value = parse(fixture["encrypted"].as_str().unwrap());
+
+        let plaintext = value.decrypt_raw(&key).unwrap_or_else(|err| panic!("{}: {}", source, err));
+        assert_eq!(plaintext, fixture["plaintext"].as_str().unwrap().as_bytes(), "{}", source);
+    }
+}
+
+#[test]
+fn encryption_requires_user_key() {
+    let cipher = CipherSuite::default();
+    assert!(matches!(cipher.encrypt(b"secret"), Err(CipherError::Locked)));
+}
+
+#[test]
+fn derived_keys_are_independent() {
+    let key = SymmetricKey::from_bytes(&[7u8; 64]).unwrap();
+    let derived = key.derive("tokens");
+
+    assert_eq!(&*derived.to_bytes(), &*key.derive("tokens").to_bytes());
+    assert_ne!(&*derived.to_bytes(), &*key.to_bytes());
+    assert_ne!(&*derived.to_bytes(), &*key.derive("other").to_bytes());
+
+    let encrypted = CipherString::encrypt(b"secret", &derived);
+    assert!(encrypted.decrypt(&key).is_none());
+}
+
+#[test]
+fn aes_cbc_256_without_mac() {
+    let key = SymmetricKey::from_bytes(&[1u8; 32]).unwrap();
+    let value = parse("0.AAECAwQFBgcICQoLDA0ODw==|0UemkmBZbR5u7f3vmDKwWw==");
+
+    assert_eq!(value.encryption_type(), EncryptionType::AesCbc256B64);
+    assert_eq!(value.decrypt(&key).unwrap(), "type 0 value");
+
+    // Values of old clients have no type prefix
+    let value = parse("AAECAwQFBgcICQoLDA0ODw==|0UemkmBZbR5u7f3vmDKwWw==");
+    assert_eq!(value.encryption_type(), EncryptionType::AesCbc256B64);
+    assert_eq!(value.decrypt(&key).unwrap(), "type 0 value");
+
+    // Keys without mac key also encrypt without mac
+    let encrypted = CipherString::encrypt(b"secret", &key);
+    assert_eq!(encrypted.encryption_type(), EncryptionType::AesCbc256B64);
+    assert_eq!(encrypted.decrypt(&key).unwrap(), "secret");
+}
+
+#[test]
+fn aes_cbc_128_hmac_sha256() {
+    let key = SymmetricKey::from_bytes(&[1u8; 32]).unwrap();
+    let value = parse(
+        "1.AAECAwQFBgcICQoLDA0ODw==|VSQnpyeJpj5V9a/JvolNUw==|\
+         iYZkuKTBzp4EJ6XEDK1hJZFczNS3gK8y4eIv1aRXNuU=",
+    );
+
+    assert_eq!(value.encryption_type(), EncryptionType::AesCbc128HmacSha256B64);
+    assert_eq!(value.decrypt(&key).unwrap(), "type 1 value");
+
+    let value = parse(
+        "AAECAwQFBgcICQoLDA0ODw==|VSQnpyeJpj5V9a/JvolNUw==|\
+         iYZkuKTBzp4EJ6XEDK1hJZFczNS3gK8y4eIv1aRXNuU=",
+    );
+    assert_eq!(value.encryption_type(), EncryptionType::AesCbc128HmacSha256B64);
+    assert_eq!(value.decrypt(&key).unwrap(), "type 1 value");
+}
+
+#[test]
+fn aes_cbc_256_hmac_sha256() {
+    let key = SymmetricKey::from_bytes(&[7u8; 64]).unwrap();
+    let value = parse(
+        "2.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==|\
+         C9u93ODcOZflP4v3mp0PcIeAwXQowROPJ6IPimyfL4c=",
+    );
+
+    assert_eq!(value.encryption_type(), EncryptionType::AesCbc256HmacSha256B64);
+    assert_eq!(value.decrypt(&key).unwrap(), "type 2 value");
+
+    let encrypted = CipherString::encrypt(b"secret", &key);
+    assert_eq!(encrypted.encryption_type(), EncryptionType::AesCbc256HmacSha256B64);
+}
+
+#[test]
+fn mac_is_verified() {
+    let key = SymmetricKey::from_bytes(&[7u8; 64]).unwrap();
+    let tampered = parse(
+        "2.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==|\
+         D9u93ODcOZflP4v3mp0PcIeAwXQowROPJ6IPimyfL4c=",
+    );
+
+    assert!(matches!(tampered.decrypt_raw(&key), Err(CipherError::InvalidMac)));
+
+    // Keys with mac key must not accept values with their mac stripped
+    let stripped = parse("0.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==");
+    assert!(matches!(stripped.decrypt_raw(&key), Err(CipherError::MissingMac)));
+}
+
+#[test]
+fn legacy_user_key_is_encrypted_with_master_key() {
+    let mut cipher = CipherSuite::from(EMAIL, PASSWORD, Kdf::Pbkdf2 { iterations: 5000 }).unwrap();
+    let key = parse(
+        "0.AAECAwQFBgcICQoLDA0ODw==|zCL8bhD0DbJRw59fPwP1D/F/edcfiPWzoQjQXTym+1QXimi9QDE9+ws7bWd7\
+         xzeJGZoFGPrlF3KWU+jt3w/4pr6d6i0PHAPBT/shWjYtXQk=",
+    );
+
+    cipher.set_decrypt_key(&key).unwrap();
+    assert_eq!(&*cipher.key_for(None).unwrap().to_bytes(), &[9u8; 64][..]);
+}
+
+#[test]
+fn rsa_wrapped_organization_keys() {
+    let mut lines = RSA_KEYS.lines().map(parse);
+    let mut cipher = CipherSuite::default();
+
+    cipher.set_user_key(SymmetricKey::from_bytes(&[9u8; 64]).unwrap());
+    cipher.set_private_key(&lines.next().unwrap()).unwrap();
+
+    let keys: Vec<_> = lines
+        .enumerate()
+        .map(|(i, key)| (Uuid::from_u128(i as u128), key))
+        .collect();
+    let types: Vec<_> = keys.iter().map(|(_, key)| key.encryption_type()).collect();
+
+    assert_eq!(
+        types,
+        [
+            EncryptionType::Rsa2048OaepSha256B64,
+            EncryptionType::Rsa2048OaepSha1B64,
+            EncryptionType::Rsa2048OaepSha256HmacSha256B64,
+            EncryptionType::Rsa2048OaepSha1HmacSha256B64,
+        ]
+    );
+
+    let skipped = cipher.set_organization_keys(keys.iter().map(|(uuid, key)| (*uuid, key)));
+    assert!(skipped.unwrap().is_empty());
+
+    for (n, (uuid, _)) in (3u8..).zip(&keys) {
+        let key = cipher.key_for(Some(*uuid)).unwrap();
+        assert_eq!(&*key.to_bytes(), &[n; 64][..]);
+    }
+}
+
+#[test]
+fn malformed_cipher_strings_are_rejected() {
+    let invalid = [
+        // Unknown type
+        "7.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==",
+        "x.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==",
+        // Invalid base64
+        "2.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==|not base64",
+        // Missing mac
+        "2.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==",
+        // Superfluous mac
+        "0.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==|AAAA",
+        "4.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==",
+        // Short IV
+        "0.AAECAwQFBgcICQoLDA0O|x2h0hcqGXF56HwyrxTAaNA==",
+        // Short mac
+        "2.AAECAwQFBgcICQoLDA0ODw==|x2h0hcqGXF56HwyrxTAaNA==|AAAA",
+    ];
+
+    for text in invalid {
+        let result = text.parse::<CipherString>();
+        assert!(matches!(result, Err(CipherError::InvalidFormat(_))), "{}", text);
+    }
+}
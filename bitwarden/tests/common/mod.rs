@@ -0,0 +1,150 @@
+// SPDX-License-Identifier: MIT
+
+//! Minimal HTTP server serving canned responses, used to test the API client without network
+//! access.
+
+#![allow(dead_code)]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Clone)]
+struct Route {
+    method: &'static str,
+    path: &'static str,
+    status: u16,
+    body: String,
+}
+
+pub struct MockServer {
+    url: String,
+    routes: Arc<Mutex<Vec<Route>>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let routes = Arc::new(Mutex::new(Vec::<Route>::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let (thread_routes, thread_requests) = (routes.clone(), requests.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                handle_connection(stream, &thread_routes, &thread_requests);
+            }
+        });
+
+        Self { url, routes, requests }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Registers a canned response. Later routes for the same method and path take precedence.
+    pub fn route(&self, method: &'static str, path: &'static str, status: u16, body: &str) -> &Self {
+        self.routes.lock().unwrap().insert(0, Route {
+            method,
+            path,
+            status,
+            body: body.to_owned(),
+        });
+
+        self
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn request_to(&self, path: &str) -> Option<Request> {
+        self.requests().into_iter().find(|r| r.path == path)
+    }
+}
+
+fn handle_connection(stream: TcpStream, routes: &Mutex<Vec<Route>>, requests: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+        return;
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or_default().to_owned();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((key, value)) = line.split_once(':') {
+            headers.push((key.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse().ok())
+        .unwrap_or(0);
+
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body).unwrap();
+
+    let route = routes
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|r| r.method == method && r.path == path)
+        .cloned();
+
+    requests.lock().unwrap().push(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    });
+
+    let (status, body) = route
+        .map(|r| (r.status, r.body))
+        .unwrap_or((404, String::new()));
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body,
+    );
+
+    let mut stream = stream;
+    let _ = stream.write_all(response.as_bytes());
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: MIT
+
+use bitwarden::totp::Totp;
+
+// Secrets from RFC 6238 appendix B, base32-encoded
+const SHA1_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+const SHA256_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA====";
+const SHA512_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+                             GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA=";
+
+const RFC6238_VECTORS: &[(u64, &str, &str, &str)] = &[
+    (59, "94287082", "46119246", "90693936"),
+    (1111111109, "07081804", "68084774", "25091201"),
+    (1111111111, "14050471", "67062674", "99943326"),
+    (1234567890, "89005924", "91819424", "93441116"),
+    (2000000000, "69279037", "90698825", "38618901"),
+    (20000000000, "65353130", "77737706", "47863826"),
+];
+
+fn otpauth(secret: &str, algorithm: &str) -> Totp {
+    let uri = format!(
+        "otpauth://totp/Example:user@example.com?secret={}&issuer=Example&algorithm={}&digits=8",
+        secret.trim_end_matches('='),
+        algorithm,
+    );
+
+    Totp::parse(&uri).unwrap()
+}
+
+#[test]
+fn rfc6238_test_vectors() {
+    let sha1 = otpauth(SHA1_SECRET, "SHA1");
+    let sha256 = otpauth(SHA256_SECRET, "SHA256");
+    let sha512 = otpauth(SHA512_SECRET, "SHA512");
+
+    for &(time, expected_sha1, expected_sha256, expected_sha512) in RFC6238_VECTORS {
+        assert_eq!(sha1.generate(time), expected_sha1, "SHA1 at {}", time);
+        assert_eq!(sha256.generate(time), expected_sha256, "SHA256 at {}", time);
+        assert_eq!(sha512.generate(time), expected_sha512, "SHA512 at {}", time);
+    }
+}
+
+#[test]
+fn bare_base32_secret_uses_defaults() {
+    let totp = Totp::parse("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap();
+
+    assert_eq!(totp.period(), 30);
+    assert_eq!(totp.generate(59), "287082");
+    assert_eq!(totp.generate(1111111109), "081804");
+}
+
+#[test]
+fn custom_period() {
+    let uri = format!("otpauth://totp/Example?secret={}&period=60", SHA1_SECRET);
+    let totp = Totp::parse(&uri).unwrap();
+
+    assert_eq!(totp.period(), 60);
+    assert_eq!(totp.generate(119), totp.generate(60));
+    assert_ne!(totp.generate(119), totp.generate(120));
+}
+
+#[test]
+fn steam_guard_codes() {
+    let totp = Totp::parse(&format!("steam://{}", SHA1_SECRET)).unwrap();
+
+    assert_eq!(totp.generate(59), "PV9M4");
+    assert_eq!(totp.generate(1234567890), "VHHQY");
+}
+
+#[test]
+fn invalid_keys_are_rejected() {
+    assert!(Totp::parse("not base32!").is_err());
+    assert!(Totp::parse("").is_err());
+    assert!(Totp::parse("otpauth://hotp/Example?secret=GEZDGNBV&counter=1").is_err());
+    assert!(Totp::parse("otpauth://totp/Example?issuer=Example").is_err());
+    assert!(Totp::parse(&format!("otpauth://totp/Example?secret={}&period=0", SHA1_SECRET)).is_err());
+}
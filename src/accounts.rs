@@ -0,0 +1,83 @@
+// SPDX-License-Identifier: MIT
+
+use cursive::traits::*;
+use cursive::views::{Dialog, OnEventView, SelectView};
+use cursive::Cursive;
+
+use crate::keys::{Action, OnAction};
+use crate::vault::{self, Account, VaultData};
+use crate::{clipboard, login};
+
+/// Lets the user pick one of the given accounts, or log in to another one.
+pub fn create(siv: &mut Cursive, accounts: Vec<Account>) {
+    let mut select = SelectView::new().on_submit(|siv, account: &Option<Account>| match account {
+        Some(account) => open(siv, account),
+        None => {
+            siv.take_user_data::<VaultData>();
+            login::create(siv);
+        }
+    });
+
+    for account in accounts {
+        select.add_item(account.to_string(), Some(account));
+    }
+
+    select.add_item("Log in to another account", None);
+
+    let view = OnEventView::new(select.with_name("account_list"))
+        .on_action(Action::MoveDown, |siv| {
+            let callback = siv
+                .find_name::<SelectView<Option<Account>>>("account_list")
+                .unwrap()
+                .select_down(1);
+            callback(siv);
+        })
+        .on_action(Action::MoveUp, |siv| {
+            let callback = siv
+                .find_name::<SelectView<Option<Account>>>("account_list")
+                .unwrap()
+                .select_up(1);
+            callback(siv);
+        });
+
+    siv.clear();
+    siv.add_layer(Dialog::around(view).title("bitwarden accounts").min_width(60));
+}
+
+/// Shows the vault of the account, if the agent still keeps it unlocked, or its login dialog.
+pub fn open(siv: &mut Cursive, account: &Account) {
+    match vault::read_local_data(account) {
+        Ok(mut vault) => {
+            let unlocked = login::unlock_with_agent(&mut vault);
+            siv.set_user_data(vault);
+
+            match unlocked {
+                Some(result) => {
+                    vault::create(siv);
+
+                    if let Err(err) = result {
+                        siv.add_layer(Dialog::info(err.to_string()));
+                    }
+                }
+                None => login::create(siv),
+            }
+        }
+        Err(err) => {
+            siv.take_user_data::<VaultData>();
+            login::create(siv);
+            login::prefill(siv, Some(&account.server), Some(&account.email));
+            siv.add_layer(Dialog::info(err.to_string()));
+        }
+    }
+}
+
+/// Drops all decrypted data of the current account and goes back to the account list.
+pub fn switch(siv: &mut Cursive) {
+    clipboard::clear();
+
+    // The item details enable auto-refresh for their TOTP countdown
+    siv.set_autorefresh(false);
+    siv.take_user_data::<VaultData>();
+
+    create(siv, vault::read_accounts());
+}
@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: MIT
+
+//! Background agent keeping the user keys of unlocked vaults, so other `bwtui` processes do not
+//! need the master password again until they expire.
+//!
+//! Clients connect to a Unix socket only accessible by the user and send one JSON request per
+//! line, each answered by a JSON response line.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use bitwarden::cipher::{SecretBytes, SymmetricKey};
+
+use crate::error::Error;
+use crate::vault;
+
+const SOCKET_NAME: &str = "agent.sock";
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    /// Keeps the base64-encoded user key of an account.
+    Store { account: Uuid, key: String },
+    /// Asks for the user key of an account.
+    Get { account: Uuid },
+    /// Forgets the kept key of an account, or all kept keys without one.
+    Lock {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        account: Option<Uuid>,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Ok {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+    Locked,
+    Error { message: String },
+}
+
+struct Unlocked {
+    key: SymmetricKey,
+    expires: Option<Instant>,
+}
+
+type State = Arc<Mutex<HashMap<Uuid, Unlocked>>>;
+
+fn socket_path() -> Result<PathBuf, Error> {
+    Ok(vault::get_app_data_path()?.join(SOCKET_NAME))
+}
+
+/// Runs the agent in the foreground, keeping stored keys for the given number of seconds (or
+/// until locked, if 0).
+pub fn run(timeout: u64) -> Result<(), Error> {
+    let path = socket_path()?;
+
+    if UnixStream::connect(&path).is_ok() {
+        return Err(Error::AgentRunning(path));
+    }
+
+    // Left behind by an agent which did not shut down cleanly
+    match fs::remove_file(&path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => return Err(Error::storage(path)(err)),
+        _ => {}
+    }
+
+    // The socket must never be accessible by other users, not even right after binding it
+    let mask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    unsafe { libc::umask(mask) };
+
+    let listener = listener.map_err(Error::storage(&path))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+        .map_err(Error::storage(&path))?;
+
+    let state: State = Arc::new(Mutex::new(HashMap::new()));
+    let timeout = Some(timeout).filter(|&t| t > 0).map(Duration::from_secs);
+
+    let expiry_state = state.clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+
+        // Dropping the keys zeroizes them
+        let now = Instant::now();
+        expiry_state.lock().unwrap().retain(|_, u| u.expires.map_or(true, |e| now < e));
+    });
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        if peer_uid(&stream) != Some(unsafe { libc::getuid() }) {
+            continue;
+        }
+
+        // Each client is handled on its own, so an idle one cannot block the others
+        let state = state.clone();
+        thread::spawn(move || {
+            let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+            let _ = handle_client(stream, &state, timeout);
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_client(stream: UnixStream, state: &State, timeout: Option<Duration>) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let response = match serde_json::from_str(&line?) {
+            Ok(request) => handle_request(request, state, timeout),
+            Err(err) => Response::Error { message: format!("invalid request: {}", err) },
+        };
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn handle_request(request: Request, state: &State, timeout: Option<Duration>) -> Response {
+    let mut unlocked = state.lock().unwrap();
+
+    match request {
+        Request::Store { account, key } => {
+            let key = base64::decode(key)
+                .map(SecretBytes::from)
+                .map_err(|err| err.to_string())
+                .and_then(|key| SymmetricKey::from_bytes(&key).map_err(|err| err.to_string()));
+
+            match key {
+                Ok(key) => {
+                    let expires = timeout.map(|timeout| Instant::now() + timeout);
+                    unlocked.insert(account, Unlocked { key, expires });
+
+                    Response::Ok { key: None }
+                }
+                Err(err) => Response::Error { message: format!("invalid key: {}", err) },
+            }
+        }
+        Request::Get { account } => match unlocked.get(&account) {
+            Some(unlocked) => Response::Ok {
+                key: Some(base64::encode(&*unlocked.key.to_bytes())),
+            },
+            None => Response::Locked,
+        },
+        Request::Lock { account: Some(account) } => {
+            unlocked.remove(&account);
+            Response::Ok { key: None }
+        }
+        Request::Lock { account: None } => {
+            unlocked.clear();
+            Response::Ok { key: None }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> Option<libc::uid_t> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    (result == 0).then_some(cred.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> Option<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+
+    let result = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+
+    (result == 0).then_some(uid)
+}
+
+/// Sends a single request to the running agent.
+fn request(request: &Request) -> Result<Response, Error> {
+    let stream = UnixStream::connect(socket_path()?).map_err(Error::Agent)?;
+    let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+
+    let exchange = || -> io::Result<Response> {
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        (&stream).write_all(line.as_bytes())?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+
+        Ok(serde_json::from_str(&line)?)
+    };
+
+    exchange().map_err(Error::Agent)
+}
+
+/// Hands the user key of an account to the agent, if one is running.
+pub fn store(account: Uuid, key: &SymmetricKey) {
+    let _ = request(&Request::Store {
+        account,
+        key: base64::encode(&*key.to_bytes()),
+    });
+}
+
+/// Asks the agent for the user key of an account. Returns `None` if no agent is running or it
+/// does not keep the key (anymore).
+pub fn fetch(account: Uuid) -> Option<SymmetricKey> {
+    match request(&Request::Get { account }) {
+        Ok(Response::Ok { key: Some(key) }) => {
+            let key = SecretBytes::from(base64::decode(key).ok()?);
+            SymmetricKey::from_bytes(&key).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Makes the agent forget the kept key of an account, if one is running.
+pub fn lock(account: Uuid) {
+    let _ = request(&Request::Lock { account: Some(account) });
+}
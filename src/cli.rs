@@ -0,0 +1,317 @@
+// SPDX-License-Identifier: MIT
+
+use std::env;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::json;
+use unicase::UniCase;
+
+use bitwarden::cipher::{CipherSuite, SecretString};
+use bitwarden::totp::Totp;
+use bitwarden::{ApiError, ServerConfig};
+
+use crate::error::Error;
+use crate::{agent, config, login};
+use crate::vault::{self, Account, VaultData, VaultEntry};
+
+/// Environment variable the master password is read from instead of prompting for it.
+const PASSWORD_VAR: &str = "BWTUI_PASSWORD";
+
+#[derive(Parser)]
+#[clap(version, about)]
+pub struct Args {
+    /// Url of the bitwarden server, defaults to bitwarden.com
+    #[clap(long, value_name = "URL", global = true)]
+    pub server: Option<String>,
+
+    /// Url of the identity service, overrides the one derived from --server
+    #[clap(long, value_name = "URL", global = true)]
+    pub identity_url: Option<String>,
+
+    /// Url of the API, overrides the one derived from --server
+    #[clap(long, value_name = "URL", global = true)]
+    pub api_url: Option<String>,
+
+    /// Email address of the account
+    #[clap(long, global = true)]
+    pub email: Option<String>,
+
+    /// Path of the configuration file
+    #[clap(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Directory the vault data is stored in
+    #[clap(long, value_name = "DIR", global = true)]
+    pub data_dir: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Prints a value of the item with the given name or id
+    Get {
+        #[clap(value_enum)]
+        field: Field,
+        name: String,
+    },
+    /// Lists all items of the vault
+    List {
+        /// Print the items as a JSON array
+        #[clap(long)]
+        json: bool,
+    },
+    /// Downloads the current vault data from the server
+    Sync,
+    /// Runs the unlock agent in the foreground
+    Agent,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Field {
+    Username,
+    Password,
+    Totp,
+    Notes,
+}
+
+impl Field {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Username => "username",
+            Self::Password => "password",
+            Self::Totp => "totp",
+            Self::Notes => "notes",
+        }
+    }
+}
+
+pub fn run(args: &Args, command: &Command) -> Result<(), Error> {
+    match command {
+        Command::Get { field, name } => {
+            let vault = unlock(args)?.vault;
+            let entry = find_entry(&vault, name)?;
+
+            println!("{}", get_value(entry, *field)?);
+        }
+        Command::List { json } => {
+            let vault = unlock(args)?.vault;
+            let mut entries: Vec<_> = vault.decrypted.iter().collect();
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+            if *json {
+                let items: Vec<_> = entries
+                    .iter()
+                    .map(|entry| {
+                        json!({
+                            "id": entry.uuid,
+                            "type": entry.type_.to_string(),
+                            "name": entry.name.as_str(),
+                            "details": entry.details.as_str(),
+                            "folderId": entry.folder,
+                        })
+                    })
+                    .collect();
+
+                println!("{}", serde_json::Value::Array(items));
+            } else {
+                for entry in entries {
+                    println!("{}\t{}\t{}\t{}", entry.uuid, entry.type_, entry.name, entry.details);
+                }
+            }
+        }
+        Command::Sync => {
+            // The stored tokens are encrypted, so syncing requires unlocking the vault as well
+            let Unlocked { mut vault, synced, password } = unlock(args)?;
+
+            if !synced {
+                match bitwarden::sync(&mut vault.auth) {
+                    Ok(sync) => {
+                        vault.sync = sync;
+                        vault::save_local_data(&mut vault)?;
+                    }
+                    // The server rejected the stored tokens, so start a new session
+                    Err(ApiError::SessionExpired) => {
+                        let account = Account::of(&vault);
+                        let password = match password {
+                            Some(password) => password,
+                            None => read_password()?,
+                        };
+
+                        login(&account.server, &account.email, &password)?;
+                    }
+                    Err(err) => {
+                        // The access token might have been refreshed before syncing failed.
+                        // Failing to store it only means having to refresh it again.
+                        let _ = vault::save_local_data(&mut vault);
+                        return Err(err.into());
+                    }
+                }
+            }
+        }
+        Command::Agent => agent::run(config::get().agent_timeout)?,
+    }
+
+    Ok(())
+}
+
+/// Returns the server given on the command line, if any.
+pub fn server_config(args: &Args) -> Option<ServerConfig> {
+    if args.server.is_none() && args.identity_url.is_none() && args.api_url.is_none() {
+        return None;
+    }
+
+    Some(login::server_config(
+        args.server.as_deref().unwrap_or_default(),
+        args.identity_url.as_deref().unwrap_or_default(),
+        args.api_url.as_deref().unwrap_or_default(),
+    ))
+}
+
+/// Returns the stored accounts on the server and with the email given on the command line, if
+/// any.
+pub fn selected_accounts(args: &Args) -> Vec<Account> {
+    let server = server_config(args);
+
+    vault::read_accounts()
+        .into_iter()
+        .filter(|account| account.matches(server.as_ref(), args.email.as_deref()))
+        .collect()
+}
+
+fn cached_data(args: &Args) -> Result<Option<VaultData>, Error> {
+    match &selected_accounts(args)[..] {
+        [] => Ok(None),
+        [account] => vault::read_local_data(account).map(Some),
+        _ => Err(Error::AmbiguousAccount),
+    }
+}
+
+/// Vault data unlocked for a subcommand.
+struct Unlocked {
+    vault: VaultData,
+    /// Whether the vault data was just synced by logging in.
+    synced: bool,
+    /// The master password, unless the agent unlocked the vault.
+    password: Option<SecretString>,
+}
+
+/// Logs in with the master password, then syncs, decrypts and stores the vault data. A second
+/// factor is only possible using a device remembered in an earlier login.
+fn login(server: &ServerConfig, email: &str, password: &str) -> Result<VaultData, Error> {
+    let mut device = vault::read_device().unwrap_or_default();
+    let result = bitwarden::authenticate(server, &mut device, email, password, None);
+
+    // Failing to persist the device only means having to enter the second factor again
+    let _ = vault::save_device(&device);
+
+    let auth = result.map_err(|err| match err {
+        ApiError::TwoFactorRequired(_) => Error::TwoFactorRequired,
+        err => err.into(),
+    })?;
+
+    let mut vault = login::sync_vault_data(auth)?;
+    decrypt(&mut vault, password)?;
+    vault::save_local_data(&mut vault)?;
+
+    Ok(vault)
+}
+
+/// Decrypts the vault data with the master password and hands the user key to the agent.
+fn decrypt(vault: &mut VaultData, password: &str) -> Result<(), Error> {
+    let email = vault.sync.profile.email.clone();
+    vault.auth.cipher = CipherSuite::from(&email, password, vault.auth.kdf)?;
+
+    if vault.auth.cipher.set_decrypt_key(&vault.sync.profile.key).is_err() {
+        return Err(ApiError::LoginFailed.into());
+    }
+
+    warn(vault::decrypt(vault));
+
+    if let Some(key) = vault.auth.cipher.key_for(None) {
+        agent::store(vault.sync.profile.uuid, key);
+    }
+
+    Ok(())
+}
+
+/// Decrypts the cached vault data, logging in first if there is none. The master password is
+/// not needed while the agent keeps the vault unlocked.
+fn unlock(args: &Args) -> Result<Unlocked, Error> {
+    let mut vault = match cached_data(args)? {
+        Some(vault) => vault,
+        None => {
+            let email = args.email.as_deref().ok_or(Error::NoAccount)?;
+            let server = server_config(args).unwrap_or_default();
+            let password = read_password()?;
+            let vault = login(&server, email, &password)?;
+
+            return Ok(Unlocked { vault, synced: true, password: Some(password) });
+        }
+    };
+
+    let password = match login::unlock_with_agent(&mut vault) {
+        Some(result) => {
+            warn(result);
+            None
+        }
+        None => {
+            let password = read_password()?;
+            decrypt(&mut vault, &password)?;
+            Some(password)
+        }
+    };
+
+    Ok(Unlocked { vault, synced: false, password })
+}
+
+/// Reports entries which could not be decrypted, without giving up on the rest of the vault.
+fn warn(result: Result<(), Error>) {
+    if let Err(err) = result {
+        eprintln!("bwtui: {}", err);
+    }
+}
+
+fn read_password() -> Result<SecretString, Error> {
+    match env::var(PASSWORD_VAR) {
+        Ok(password) => Ok(password.into()),
+        Err(_) => rpassword::prompt_password("master password: ")
+            .map(SecretString::from)
+            .map_err(Error::Password),
+    }
+}
+
+/// Finds the item with the given id or, ignoring case, name.
+fn find_entry<'a>(vault: &'a VaultData, name: &str) -> Result<&'a VaultEntry, Error> {
+    if let Some(entry) = vault.decrypted.iter().find(|entry| entry.uuid.to_string() == name) {
+        return Ok(entry);
+    }
+
+    let name = UniCase::new(name.to_owned());
+    let mut matches = vault.decrypted.iter().filter(|entry| entry.name == name);
+
+    match (matches.next(), matches.next()) {
+        (Some(entry), None) => Ok(entry),
+        (Some(_), Some(_)) => Err(Error::AmbiguousItem(name.to_string())),
+        (None, _) => Err(Error::NoSuchItem(name.to_string())),
+    }
+}
+
+fn get_value(entry: &VaultEntry, field: Field) -> Result<String, Error> {
+    let value = match field {
+        Field::Username => entry.username().map(str::to_owned),
+        Field::Password => entry.password().map(str::to_owned),
+        Field::Totp => match entry.totp() {
+            Some(key) => Some(Totp::parse(key)?.now().0),
+            None => None,
+        },
+        Field::Notes => entry.notes.as_deref().map(str::to_owned),
+    };
+
+    value.ok_or_else(|| Error::MissingField {
+        item: entry.name.to_string(),
+        field: field.name(),
+    })
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: MIT
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use ::clipboard::{ClipboardContext, ClipboardProvider};
+use cursive::views::{Dialog, TextView};
+use cursive::{CbSink, Cursive};
+
+use bitwarden::cipher::SecretString;
+
+use crate::config;
+use crate::error::Error;
+
+/// The value last put into the clipboard, along with the id of the copy.
+static COPIED: Mutex<Option<(u64, SecretString)>> = Mutex::new(None);
+
+/// Ids of the copies, never reused, so the countdown of an earlier copy cannot clear a later one.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Copies a value into the clipboard and clears it again after the configured timeout, showing
+/// the countdown in the status bar.
+pub fn copy(siv: &mut Cursive, value: &str) {
+    if let Err(err) = set_contents(value) {
+        siv.add_layer(Dialog::info(err.to_string()));
+        return;
+    }
+
+    let timeout = config::get().clipboard_timeout;
+    if timeout == 0 {
+        return;
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    *COPIED.lock().unwrap() = Some((id, value.into()));
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        for remaining in (1..=timeout).rev() {
+            if !is_current(id) {
+                return;
+            }
+
+            set_status(&sink, format!("clipboard clears in {}s", remaining));
+            thread::sleep(Duration::from_secs(1));
+        }
+
+        if is_current(id) {
+            clear();
+            set_status(&sink, String::new());
+        }
+    });
+}
+
+fn set_contents(value: &str) -> Result<(), Error> {
+    let mut clipboard: ClipboardContext =
+        ClipboardProvider::new().map_err(|err| Error::Clipboard(err.to_string()))?;
+
+    clipboard
+        .set_contents(value.to_owned())
+        .map_err(|err| Error::Clipboard(err.to_string()))
+}
+
+/// Clears the clipboard if it still holds the value copied last.
+pub fn clear() {
+    let copied = COPIED.lock().unwrap().take();
+
+    if let Some((_, value)) = copied {
+        if let Ok(mut clipboard) = ClipboardContext::new() {
+            if clipboard.get_contents().is_ok_and(|contents| *value == *contents) {
+                let _ = clipboard.set_contents(String::new());
+            }
+        }
+    }
+}
+
+fn is_current(id: u64) -> bool {
+    matches!(*COPIED.lock().unwrap(), Some((current, _)) if current == id)
+}
+
+fn set_status(sink: &CbSink, status: String) {
+    // Sending fails if the UI is already gone, which is fine
+    let _ = sink.send(Box::new(move |siv| {
+        siv.call_on_name("clipboard_status", |view: &mut TextView| {
+            view.set_content(status);
+        });
+    }));
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::keys::KeyBindings;
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Seconds after which copied values are cleared from the clipboard, 0 to never clear it.
+    pub clipboard_timeout: u64,
+    /// Seconds without any input after which the vault gets locked, 0 to never lock it.
+    pub lock_timeout: u64,
+    /// Seconds the unlock agent keeps the vault unlocked, 0 to keep it unlocked until locked
+    /// explicitly.
+    pub agent_timeout: u64,
+    /// Keys bound to each action, overriding the defaults.
+    pub keys: KeyBindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clipboard_timeout: 30,
+            lock_timeout: 300,
+            agent_timeout: 900,
+            keys: KeyBindings::default(),
+        }
+    }
+}
+
+fn get_config_path() -> Result<PathBuf, Error> {
+    let project_dirs = directories::ProjectDirs::from("", "", "bwtui")
+        .ok_or(Error::NoProjectDir("config"))?;
+
+    Ok(project_dirs.config_dir().join("config.toml"))
+}
+
+/// Reads the given configuration file or the default one, if there is one. The defaults stay in
+/// effect if it cannot be parsed.
+pub fn load(path: Option<&Path>) -> Result<(), Error> {
+    let explicit = path.is_some();
+    let path = match path {
+        Some(path) => path.to_owned(),
+        None => get_config_path()?,
+    };
+
+    let config = match fs::read_to_string(&path) {
+        Ok(content) => toml::from_str(&content).map_err(|source| Error::Config {
+            path: path.clone(),
+            source,
+        })?,
+        Err(err) if err.kind() == ErrorKind::NotFound && !explicit => Config::default(),
+        Err(err) => return Err(Error::storage(path)(err)),
+    };
+
+    let _ = CONFIG.set(config);
+
+    Ok(())
+}
+
+/// Reports conflicting key bindings, which are kept nonetheless. Only the interactive interface
+/// uses them.
+pub fn check_keys() -> Result<(), Error> {
+    let conflicts = get().keys.conflicts();
+
+    if conflicts.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::KeyConflicts(conflicts))
+    }
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
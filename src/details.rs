@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: MIT
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use chrono::{DateTime, Utc};
+use cursive::event::Event;
+use cursive::traits::*;
+use cursive::views::{Dialog, DummyView, LinearLayout, OnEventView, SelectView, TextView};
+use cursive::Cursive;
+
+use bitwarden::cipher::SecretString;
+use bitwarden::totp::Totp;
+use bitwarden::{Card, Identity, ItemData, Login};
+
+use crate::clipboard;
+use crate::config;
+use crate::keys::{Action, OnAction};
+use crate::vault::VaultEntry;
+
+const MASK: &str = "********";
+
+struct Field {
+    label: String,
+    value: SecretString,
+    hidden: bool,
+    totp: Option<Totp>,
+}
+
+impl Field {
+    fn display(&self, label_width: usize, reveal: bool) -> String {
+        let value = if let Some(totp) = &self.totp {
+            let (code, remaining) = totp.now();
+            format!("{} ({}s)", code, remaining)
+        } else if self.hidden && !reveal {
+            MASK.to_owned()
+        } else {
+            // Multi-line values (like notes) are shown in full below the field list
+            self.value.lines().next().unwrap_or_default().to_owned()
+        };
+
+        format!("{:<width$}  {}", self.label, value, width = label_width)
+    }
+
+    /// The value put into the clipboard, which for TOTP fields is the current code.
+    fn copy_value(&self) -> SecretString {
+        match &self.totp {
+            Some(totp) => totp.now().0.into(),
+            None => self.value.clone(),
+        }
+    }
+}
+
+/// Collects the non-empty fields of an item in display order.
+#[derive(Default)]
+struct FieldList(Vec<Field>);
+
+impl FieldList {
+    fn push(&mut self, label: &str, value: Option<&str>) {
+        self.push_field(label, value, false);
+    }
+
+    fn push_hidden(&mut self, label: &str, value: Option<&str>) {
+        self.push_field(label, value, true);
+    }
+
+    fn push_field(&mut self, label: &str, value: Option<&str>, hidden: bool) {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            self.0.push(Field {
+                label: label.to_owned(),
+                value: value.into(),
+                hidden,
+                totp: None,
+            });
+        }
+    }
+
+    fn push_totp(&mut self, label: &str, totp: Totp) {
+        self.0.push(Field {
+            label: label.to_owned(),
+            value: SecretString::default(),
+            hidden: false,
+            totp: Some(totp),
+        });
+    }
+}
+
+pub fn create(siv: &mut Cursive, entry: &VaultEntry) {
+    let fields = Rc::new(collect_fields(entry));
+    let reveal = Rc::new(Cell::new(false));
+    let has_totp = fields.iter().any(|field| field.totp.is_some());
+
+    // Items only refer to the fields by index, so the values are not copied on every refresh
+    let submit_fields = fields.clone();
+    let mut select = SelectView::new().on_submit(move |siv, index: &usize| {
+        clipboard::copy(siv, &submit_fields[*index].copy_value());
+    });
+    populate(&mut select, &fields, false);
+
+    let mut layout = LinearLayout::vertical().child(select.with_name("detail_fields").scrollable());
+
+    if let Some(notes) = entry.notes.as_ref().filter(|notes| notes.contains('\n')) {
+        layout.add_child(DummyView);
+        layout.add_child(TextView::new("notes:"));
+        layout.add_child(TextView::new(notes.to_string()).scrollable());
+    }
+
+    layout.add_child(DummyView);
+    layout.add_child(TextView::new(format!(
+        "<enter>: Copy field  {}",
+        config::get().keys.help(&[
+            (Action::ToggleHidden, "Show/hide hidden values"),
+            (Action::Close, "Close"),
+        ]),
+    )));
+
+    let refresh_fields = fields.clone();
+    let refresh_reveal = reveal.clone();
+
+    let view = OnEventView::new(layout)
+        .on_action(Action::MoveDown, |siv| {
+            siv.call_on_name("detail_fields", |view: &mut SelectView<usize>| {
+                view.select_down(1);
+            });
+        })
+        .on_action(Action::MoveUp, |siv| {
+            siv.call_on_name("detail_fields", |view: &mut SelectView<usize>| {
+                view.select_up(1);
+            });
+        })
+        .on_action(Action::ToggleHidden, move |siv| {
+            reveal.set(!reveal.get());
+
+            repopulate(siv, &fields, reveal.get());
+        })
+        // Keeps the TOTP code and its countdown current
+        .on_event(Event::Refresh, move |siv| {
+            repopulate(siv, &refresh_fields, refresh_reveal.get());
+        })
+        .on_action(Action::Close, close);
+
+    let dialog = Dialog::around(view)
+        .title(entry.name.to_string())
+        .min_width(60);
+
+    if has_totp {
+        siv.set_fps(1);
+    }
+
+    siv.add_layer(dialog);
+}
+
+fn close(siv: &mut Cursive) {
+    siv.set_autorefresh(false);
+    siv.pop_layer();
+}
+
+fn repopulate(siv: &mut Cursive, fields: &[Field], reveal: bool) {
+    siv.call_on_name("detail_fields", |view: &mut SelectView<usize>| {
+        let selected = view.selected_id().unwrap_or(0);
+
+        populate(view, fields, reveal);
+        view.set_selection(selected);
+    });
+}
+
+fn populate(view: &mut SelectView<usize>, fields: &[Field], reveal: bool) {
+    let label_width = fields.iter().map(|f| f.label.chars().count()).max().unwrap_or(0);
+
+    view.clear();
+    for (index, field) in fields.iter().enumerate() {
+        view.add_item(field.display(label_width, reveal), index);
+    }
+}
+
+fn collect_fields(entry: &VaultEntry) -> Vec<Field> {
+    let mut fields = FieldList::default();
+
+    fields.push("name", Some(&entry.name));
+    fields.push("type", Some(&entry.type_.to_string()));
+
+    match &entry.data {
+        ItemData::Login(login) => login_fields(&mut fields, login),
+        ItemData::SecureNote => {},
+        ItemData::Card(card) => card_fields(&mut fields, card),
+        ItemData::Identity(identity) => identity_fields(&mut fields, identity),
+    }
+
+    fields.push("notes", entry.notes.as_deref());
+
+    for field in &entry.fields {
+        let label = field.name.as_deref().unwrap_or("(unnamed field)");
+
+        if field.is_hidden() {
+            fields.push_hidden(label, field.value.as_deref());
+        } else {
+            fields.push(label, field.value.as_deref());
+        }
+    }
+
+    for old in &entry.password_history {
+        let label = format!("password until {}", format_date(&old.last_used_date));
+        fields.push_hidden(&label, Some(&old.password));
+    }
+
+    fields.push("last modified", Some(&format_date(&entry.last_changed)));
+
+    fields.0
+}
+
+fn login_fields(fields: &mut FieldList, login: &Login<SecretString>) {
+    fields.push("username", login.username.as_deref());
+    fields.push_hidden("password", login.password.as_deref());
+    fields.push_hidden("totp secret", login.totp.as_deref());
+
+    if let Some(totp) = login.totp.as_deref().and_then(|key| Totp::parse(key).ok()) {
+        fields.push_totp("totp", totp);
+    }
+
+    for uri in login.uris.iter().flatten() {
+        fields.push("uri", uri.uri.as_deref());
+    }
+
+    if let Some(date) = &login.password_last_changed {
+        fields.push("password changed", Some(&format_date(date)));
+    }
+}
+
+fn card_fields(fields: &mut FieldList, card: &Card<SecretString>) {
+    fields.push("cardholder name", card.cardholder_name.as_deref());
+    fields.push("brand", card.brand.as_deref());
+    fields.push_hidden("number", card.number.as_deref());
+
+    let expiration = join(&[&card.exp_month, &card.exp_year], "/");
+
+    fields.push("expiration", expiration.as_deref());
+    fields.push_hidden("security code", card.code.as_deref());
+}
+
+fn identity_fields(fields: &mut FieldList, identity: &Identity<SecretString>) {
+    let name = join(
+        &[&identity.title, &identity.first_name, &identity.middle_name, &identity.last_name],
+        " ",
+    );
+
+    fields.push("full name", name.as_deref());
+    fields.push("username", identity.username.as_deref());
+    fields.push("company", identity.company.as_deref());
+    fields.push("email", identity.email.as_deref());
+    fields.push("phone", identity.phone.as_deref());
+    fields.push_hidden("ssn", identity.ssn.as_deref());
+    fields.push_hidden("passport number", identity.passport_number.as_deref());
+    fields.push_hidden("license number", identity.license_number.as_deref());
+
+    let address = join(
+        &[
+            &identity.address1,
+            &identity.address2,
+            &identity.address3,
+            &identity.postal_code,
+            &identity.city,
+            &identity.state,
+            &identity.country,
+        ],
+        ", ",
+    );
+
+    fields.push("address", address.as_deref());
+}
+
+fn join(parts: &[&Option<SecretString>], separator: &str) -> Option<SecretString> {
+    let parts: Vec<_> = parts
+        .iter()
+        .filter_map(|part| part.as_deref())
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(separator).into())
+    }
+}
+
+fn format_date(date: &DateTime<Utc>) -> String {
+    date.format("%Y-%m-%d %H:%M UTC").to_string()
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: MIT
+
+//! Form for creating login items and editing the values of existing items.
+
+use std::thread;
+
+use cursive::traits::*;
+use cursive::views::{Checkbox, Dialog, EditView, LinearLayout, OnEventView, TextArea, TextView};
+use cursive::Cursive;
+use uuid::Uuid;
+
+use bitwarden::{CipherEntry, ItemData, ItemEdit};
+
+use crate::error::Error;
+use crate::keys::{Action, OnAction};
+use crate::vault::{self, CollectionFilter, FolderFilter, VaultData, VaultEntry};
+
+/// Shows the form for a new login item. It is created in the selected folder and, if one is
+/// selected, in the collection of an organization.
+pub fn create_new(siv: &mut Cursive) {
+    show_form(siv, None, &ItemEdit::default(), true);
+}
+
+/// Shows the form for editing the item, if the user may change it.
+pub fn create(siv: &mut Cursive, entry: &VaultEntry) {
+    let item = siv
+        .user_data::<VaultData>()
+        .and_then(|vault| vault.sync.ciphers.iter().find(|c| c.uuid == entry.uuid).cloned());
+
+    let item = match item {
+        Some(item) => item,
+        None => return,
+    };
+
+    // Items in read-only collections
+    if !item.edit {
+        siv.add_layer(Dialog::info("this item can only be viewed, not edited"));
+        return;
+    }
+
+    let login = match &entry.data {
+        ItemData::Login(login) => Some(login),
+        _ => None,
+    };
+
+    let uris = login
+        .and_then(|login| login.uris.as_ref())
+        .map(|uris| uris.iter().filter_map(|uri| uri.uri.as_deref().map(str::to_owned)).collect())
+        .unwrap_or_default();
+
+    let values = ItemEdit {
+        name: entry.name.to_string(),
+        notes: entry.notes.clone().unwrap_or_default(),
+        favorite: item.favorite,
+        username: entry.username().unwrap_or_default().to_owned(),
+        password: entry.password().unwrap_or_default().into(),
+        uris,
+    };
+
+    show_form(siv, Some(entry.uuid), &values, login.is_some());
+}
+
+fn input(name: &'static str, content: &str, secret: bool) -> impl View {
+    let mut edit = EditView::new().content(content);
+    if secret {
+        edit.set_secret(true);
+    }
+
+    OnEventView::new(edit.with_name(name)).on_action(Action::ClearInput, move |siv| {
+        siv.find_name::<EditView>(name).unwrap().set_content("")(siv);
+    })
+}
+
+fn show_form(siv: &mut Cursive, uuid: Option<Uuid>, values: &ItemEdit, is_login: bool) {
+    let mut layout = LinearLayout::vertical()
+        .child(TextView::new("name:"))
+        .child(input("edit_name", &values.name, false));
+
+    if is_login {
+        layout.add_child(TextView::new("username:"));
+        layout.add_child(input("edit_username", &values.username, false));
+        layout.add_child(TextView::new("password:"));
+        layout.add_child(input("edit_password", &values.password, true));
+        layout.add_child(TextView::new("uris (one per line):"));
+        layout.add_child(
+            TextArea::new()
+                .content(values.uris.join("\n"))
+                .with_name("edit_uris")
+                .min_height(2),
+        );
+    }
+
+    layout.add_child(TextView::new("notes:"));
+    layout.add_child(
+        TextArea::new()
+            .content(&*values.notes)
+            .with_name("edit_notes")
+            .min_height(3),
+    );
+    layout.add_child(
+        LinearLayout::horizontal()
+            .child(Checkbox::new().with_checked(values.favorite).with_name("edit_favorite"))
+            .child(TextView::new(" favorite")),
+    );
+
+    let title = match uuid {
+        Some(_) => format!("edit {}", values.name),
+        None => "new login".to_owned(),
+    };
+
+    let dialog = Dialog::around(layout)
+        .title(title)
+        .button("Save", move |siv| save(siv, uuid))
+        .dismiss_button("Cancel")
+        .min_width(60);
+
+    siv.add_layer(dialog);
+}
+
+fn read_form(siv: &mut Cursive) -> ItemEdit {
+    let text = |siv: &mut Cursive, name| {
+        siv.find_name::<EditView>(name)
+            .map(|view| view.get_content().to_string())
+            .unwrap_or_default()
+    };
+
+    let text_area = |siv: &mut Cursive, name| {
+        siv.find_name::<TextArea>(name)
+            .map(|view| view.get_content().to_owned())
+            .unwrap_or_default()
+    };
+
+    let uris = text_area(siv, "edit_uris")
+        .lines()
+        .map(|uri| uri.trim().to_owned())
+        .filter(|uri| !uri.is_empty())
+        .collect();
+
+    ItemEdit {
+        name: text(siv, "edit_name").trim().to_owned(),
+        notes: text_area(siv, "edit_notes").into(),
+        favorite: siv.find_name::<Checkbox>("edit_favorite").unwrap().is_checked(),
+        username: text(siv, "edit_username"),
+        password: text(siv, "edit_password").into(),
+        uris,
+    }
+}
+
+/// Encrypts the edited values into the item, or a new one.
+fn prepare(vault: &VaultData, uuid: Option<Uuid>, edit: &ItemEdit) -> Result<CipherEntry, Error> {
+    let cipher = &vault.auth.cipher;
+
+    if let Some(uuid) = uuid {
+        let mut entry = vault
+            .sync
+            .ciphers
+            .iter()
+            .find(|c| c.uuid == uuid)
+            .cloned()
+            .ok_or_else(|| Error::NoSuchItem(uuid.to_string()))?;
+
+        entry.apply_edit(edit, cipher)?;
+
+        return Ok(entry);
+    }
+
+    let collection = match vault.collection_filter {
+        CollectionFilter::Collection(uuid) => {
+            vault.sync.collections.iter().find(|c| c.uuid == uuid)
+        }
+        CollectionFilter::All => None,
+    };
+
+    let organization = collection.map(|c| c.organization_id);
+
+    let mut entry = CipherEntry::new_login(edit, organization, cipher)?;
+    entry.collection_ids = collection.map(|c| vec![c.uuid]).unwrap_or_default();
+
+    if let FolderFilter::Folder(uuid) = vault.folder_filter {
+        entry.folder_id = Some(uuid);
+    }
+
+    Ok(entry)
+}
+
+fn save(siv: &mut Cursive, uuid: Option<Uuid>) {
+    let edit = read_form(siv);
+
+    if edit.name.is_empty() {
+        siv.add_layer(Dialog::info("the name must not be empty"));
+        return;
+    }
+
+    let mut vault = match siv.take_user_data::<VaultData>() {
+        Some(vault) => vault,
+        None => return,
+    };
+
+    let entry = match prepare(&vault, uuid, &edit) {
+        Ok(entry) => entry,
+        Err(err) => {
+            siv.set_user_data(vault);
+            siv.add_layer(Dialog::info(err.to_string()));
+            return;
+        }
+    };
+
+    siv.set_autorefresh(true);
+    siv.add_layer(Dialog::around(TextView::new("saving ...")));
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let result = match uuid {
+            Some(_) => bitwarden::update_cipher(&mut vault.auth, &entry),
+            None => bitwarden::create_cipher(&mut vault.auth, &entry),
+        };
+
+        sink.send(Box::new(move |siv| {
+            siv.pop_layer();
+            siv.set_autorefresh(false);
+
+            let saved = result.is_ok();
+            let result = result.map_err(Error::from).and_then(|entry| {
+                let uuid = entry.uuid;
+
+                match vault.sync.ciphers.iter_mut().find(|c| c.uuid == uuid) {
+                    Some(old) => *old = entry,
+                    None => vault.sync.ciphers.push(entry),
+                }
+
+                // Other items which cannot be decrypted were already reported when unlocking
+                match vault::decrypt(&mut vault) {
+                    Err(Error::UndecryptableEntries(_))
+                        if vault.decrypted.iter().any(|e| e.uuid == uuid) =>
+                    {
+                        Ok(())
+                    }
+                    result => result,
+                }
+            });
+
+            // The access token might have been refreshed even if saving the item failed
+            let result = result.and(vault::save_local_data(&mut vault));
+
+            siv.set_user_data(vault);
+
+            // Failing to save the item keeps the form open, so the changes are not lost
+            if saved {
+                siv.pop_layer();
+                vault::refresh_table(siv);
+            }
+
+            if let Err(err) = result {
+                siv.add_layer(Dialog::info(err.to_string()));
+            }
+        })).unwrap();
+    });
+}
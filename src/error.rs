@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: MIT
+
+use std::io;
+use std::path::PathBuf;
+
+use bitwarden::cipher::CipherError;
+use bitwarden::totp::TotpError;
+use bitwarden::ApiError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Talking to the server failed, see `ApiError` for the network, HTTP status and parse
+    /// failures.
+    #[error(transparent)]
+    Api(#[from] ApiError),
+
+    #[error("decryption failed: {0}")]
+    Crypto(#[from] CipherError),
+
+    #[error("failed to decrypt {0} entries of the vault")]
+    UndecryptableEntries(usize),
+
+    #[error("failed to decrypt the stored access token, log in again to sync: {0}")]
+    Tokens(#[source] CipherError),
+
+    #[error(transparent)]
+    Totp(#[from] TotpError),
+
+    #[error("could not retrieve {0} directory path")]
+    NoProjectDir(&'static str),
+
+    #[error("failed to access {}: {source}", path.display())]
+    Storage {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("invalid data in {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("invalid config file {}: {source}", path.display())]
+    Config {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("conflicting key bindings:\n{}", .0.join("\n"))]
+    KeyConflicts(Vec<String>),
+
+    #[error("failed to access the clipboard: {0}")]
+    Clipboard(String),
+
+    #[error("agent already running on {}", .0.display())]
+    AgentRunning(PathBuf),
+
+    #[error("failed to talk to the agent: {0}")]
+    Agent(#[source] io::Error),
+
+    #[error("failed to read master password: {0}")]
+    Password(#[source] io::Error),
+
+    #[error("no vault data stored for this account, --email is required to log in")]
+    NoAccount,
+
+    #[error("multiple accounts stored, select one using --email and --server")]
+    AmbiguousAccount,
+
+    #[error(
+        "two-factor authentication required, log in once using the interactive interface and \
+         remember the device"
+    )]
+    TwoFactorRequired,
+
+    #[error("no item named {0}")]
+    NoSuchItem(String),
+
+    #[error("multiple items named {0}, use the id instead")]
+    AmbiguousItem(String),
+
+    #[error("{item} has no {field}")]
+    MissingField { item: String, field: &'static str },
+}
+
+impl Error {
+    /// Wraps I/O errors on the given path, for use with `map_err`.
+    pub fn storage(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| Self::Storage { path, source }
+    }
+}
@@ -0,0 +1,417 @@
+// SPDX-License-Identifier: MIT
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use cursive::event::{Event, Key};
+use cursive::views::OnEventView;
+use cursive::{Cursive, View};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use crate::config;
+
+/// Named actions which can be bound to keys in the config file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Lock,
+    Sync,
+    MoveDown,
+    MoveUp,
+    MoveFirst,
+    MoveLast,
+    CopyUsername,
+    CopyPassword,
+    CopyTotp,
+    Search,
+    ShowFilters,
+    ShowDetails,
+    FocusVault,
+    ToggleHidden,
+    Close,
+    ClearInput,
+    EndSearch,
+    SwitchAccount,
+    NewItem,
+    EditItem,
+}
+
+/// Views in which an action is available. Keys only conflict if their actions share a context.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Vault,
+    Filters,
+    Details,
+    Input,
+    Accounts,
+}
+
+const ALL_CONTEXTS: &[Context] = &[
+    Context::Vault,
+    Context::Filters,
+    Context::Details,
+    Context::Input,
+    Context::Accounts,
+];
+
+impl Action {
+    fn contexts(self) -> &'static [Context] {
+        match self {
+            Self::Quit => ALL_CONTEXTS,
+            Self::MoveDown | Self::MoveUp => {
+                &[Context::Vault, Context::Filters, Context::Details, Context::Accounts]
+            }
+            Self::Search => &[Context::Vault, Context::Input],
+            Self::FocusVault => &[Context::Filters],
+            Self::ToggleHidden | Self::Close => &[Context::Details],
+            Self::ClearInput | Self::EndSearch => &[Context::Input],
+            _ => &[Context::Vault],
+        }
+    }
+
+    fn default_keys(self) -> Vec<Event> {
+        match self {
+            Self::Quit => vec![Event::CtrlChar('c')],
+            Self::Lock => vec![Event::CtrlChar('l')],
+            Self::Sync => vec![Event::CtrlChar('r')],
+            Self::MoveDown => vec![Event::Char('j')],
+            Self::MoveUp => vec![Event::Char('k')],
+            Self::MoveFirst => vec![Event::Char('K')],
+            Self::MoveLast => vec![Event::Char('J')],
+            Self::CopyUsername => vec![Event::CtrlChar('u')],
+            Self::CopyPassword => vec![Event::CtrlChar('p')],
+            Self::CopyTotp => vec![Event::CtrlChar('t')],
+            Self::Search => vec![Event::CtrlChar('f')],
+            Self::ShowFilters => vec![Event::Char('h')],
+            Self::ShowDetails => vec![Event::Char('l')],
+            Self::FocusVault => vec![Event::Char('l'), Event::Key(Key::Enter)],
+            Self::ToggleHidden => vec![Event::Char('v')],
+            Self::Close => vec![Event::Char('q'), Event::Char('h'), Event::Key(Key::Esc)],
+            Self::ClearInput => vec![Event::CtrlChar('u')],
+            Self::EndSearch => vec![Event::Key(Key::Esc), Event::Key(Key::Enter)],
+            Self::SwitchAccount => vec![Event::CtrlChar('o')],
+            Self::NewItem => vec![Event::CtrlChar('n')],
+            Self::EditItem => vec![Event::Char('e')],
+        }
+    }
+}
+
+const ACTIONS: &[Action] = &[
+    Action::Quit,
+    Action::Lock,
+    Action::Sync,
+    Action::MoveDown,
+    Action::MoveUp,
+    Action::MoveFirst,
+    Action::MoveLast,
+    Action::CopyUsername,
+    Action::CopyPassword,
+    Action::CopyTotp,
+    Action::Search,
+    Action::ShowFilters,
+    Action::ShowDetails,
+    Action::FocusVault,
+    Action::ToggleHidden,
+    Action::Close,
+    Action::ClearInput,
+    Action::EndSearch,
+    Action::SwitchAccount,
+    Action::NewItem,
+    Action::EditItem,
+];
+
+/// A single key or a list of keys, as written in the config file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Keys bound to each action, the built-in defaults overridden by the config file.
+#[derive(Debug)]
+pub struct KeyBindings(HashMap<Action, Vec<Event>>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(ACTIONS.iter().map(|&action| (action, action.default_keys())).collect())
+    }
+}
+
+impl TryFrom<HashMap<Action, KeyList>> for KeyBindings {
+    type Error = String;
+
+    fn try_from(overrides: HashMap<Action, KeyList>) -> Result<Self, Self::Error> {
+        let mut bindings = Self::default();
+
+        for (action, keys) in overrides {
+            let keys = match keys {
+                KeyList::One(key) => vec![key],
+                KeyList::Many(keys) => keys,
+            };
+
+            let events = keys.iter().map(|key| parse_key(key)).collect::<Result<_, _>>()?;
+            bindings.0.insert(action, events);
+        }
+
+        Ok(bindings)
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBindings {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // Parsed by name, as TOML does not support enums as keys of tables
+        let overrides = HashMap::<String, KeyList>::deserialize(deserializer)?
+            .into_iter()
+            .map(|(name, keys)| {
+                let action = Action::deserialize(name.as_str().into_deserializer())
+                    .map_err(|_: serde::de::value::Error| format!("unknown action: {}", name))?;
+                Ok((action, keys))
+            })
+            .collect::<Result<HashMap<_, _>, String>>()
+            .map_err(serde::de::Error::custom)?;
+
+        Self::try_from(overrides).map_err(serde::de::Error::custom)
+    }
+}
+
+impl KeyBindings {
+    pub fn keys(&self, action: Action) -> &[Event] {
+        self.0.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    /// Describes all keys which are bound to more than one action in the same view, or which
+    /// text fields take as input before they reach an action.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        for (i, &first) in ACTIONS.iter().enumerate() {
+            for &second in &ACTIONS[i + 1..] {
+                let shares_context = first.contexts().iter().any(|c| second.contexts().contains(c));
+                if !shares_context {
+                    continue;
+                }
+
+                for key in self.keys(first).iter().filter(|key| self.keys(second).contains(key)) {
+                    conflicts.push(format!(
+                        "{} is bound to both {:?} and {:?}",
+                        describe(key),
+                        first,
+                        second,
+                    ));
+                }
+            }
+        }
+
+        for &action in ACTIONS.iter().filter(|a| a.contexts().contains(&Context::Input)) {
+            for key in self.keys(action).iter().filter(|key| is_text_input(key)) {
+                conflicts.push(format!(
+                    "{} is bound to {:?}, but text fields take it as input",
+                    describe(key),
+                    action,
+                ));
+            }
+        }
+
+        conflicts
+    }
+
+    /// Builds a help line like `^C: Quit  ^L: Lock` from the first key of each action.
+    pub fn help(&self, entries: &[(Action, &str)]) -> String {
+        entries
+            .iter()
+            .filter_map(|(action, text)| {
+                self.keys(*action).first().map(|key| format!("{}: {}", describe(key), text))
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
+/// Binds callbacks to the keys of actions.
+pub trait OnAction {
+    fn on_action<F>(self, action: Action, callback: F) -> Self
+    where
+        F: Fn(&mut Cursive) + 'static;
+}
+
+impl<T: View> OnAction for OnEventView<T> {
+    fn on_action<F>(mut self, action: Action, callback: F) -> Self
+    where
+        F: Fn(&mut Cursive) + 'static,
+    {
+        let callback = Rc::new(callback);
+
+        for key in config::get().keys.keys(action) {
+            let callback = callback.clone();
+            self.set_on_event(key.clone(), move |siv| callback(siv));
+        }
+
+        self
+    }
+}
+
+/// Registers the global quit keys in place of the cursive default.
+pub fn bind_quit(siv: &mut Cursive) {
+    siv.clear_global_callbacks(Event::CtrlChar('c'));
+
+    for key in config::get().keys.keys(Action::Quit) {
+        siv.add_global_callback(key.clone(), Cursive::quit);
+    }
+}
+
+/// Parses keys like `j`, `ctrl-p`, `alt-x`, `esc` or `f5`.
+fn parse_key(key: &str) -> Result<Event, String> {
+    let invalid = || format!("invalid key: {}", key);
+    let single_char = |s: &str| {
+        let mut chars = s.chars();
+        chars.next().filter(|_| chars.next().is_none())
+    };
+
+    if let Some(c) = single_char(key) {
+        return Ok(Event::Char(c));
+    }
+
+    let lower = key.to_ascii_lowercase();
+
+    if let Some(rest) = lower.strip_prefix("ctrl-") {
+        return single_char(rest).map(Event::CtrlChar).ok_or_else(invalid);
+    }
+
+    if let Some(rest) = key.get(4..).filter(|_| lower.starts_with("alt-")) {
+        return single_char(rest).map(Event::AltChar).ok_or_else(invalid);
+    }
+
+    let key = match lower.as_str() {
+        "space" => return Ok(Event::Char(' ')),
+        "esc" => Key::Esc,
+        "enter" => Key::Enter,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "del" => Key::Del,
+        "ins" => Key::Ins,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        _ => {
+            let n = lower
+                .strip_prefix('f')
+                .and_then(|n| n.parse().ok())
+                .filter(|n| (1..=12).contains(n))
+                .ok_or_else(invalid)?;
+
+            Key::from_f(n)
+        }
+    };
+
+    Ok(Event::Key(key))
+}
+
+/// Whether text fields handle the key themselves instead of passing it on to the actions.
+fn is_text_input(key: &Event) -> bool {
+    matches!(
+        key,
+        Event::Char(_)
+            | Event::Key(Key::Left | Key::Right | Key::Home | Key::End | Key::Backspace | Key::Del)
+    )
+}
+
+fn describe(key: &Event) -> String {
+    match key {
+        Event::Char(' ') => "<space>".to_owned(),
+        Event::Char(c) => c.to_string(),
+        Event::CtrlChar(c) => format!("^{}", c.to_ascii_uppercase()),
+        Event::AltChar(c) => format!("M-{}", c),
+        Event::Key(key) => format!("<{:?}>", key).to_lowercase(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(overrides: &[(Action, &str)]) -> KeyBindings {
+        let overrides = overrides
+            .iter()
+            .map(|&(action, key)| (action, KeyList::One(key.to_owned())))
+            .collect::<HashMap<_, _>>();
+
+        KeyBindings::try_from(overrides).unwrap()
+    }
+
+    #[test]
+    fn keys_from_config_file() {
+        let bindings: KeyBindings =
+            toml::from_str("copy_password = \"ctrl-y\"\nmove_down = [\"j\", \"down\"]").unwrap();
+
+        assert_eq!(bindings.keys(Action::CopyPassword), [Event::CtrlChar('y')]);
+        assert_eq!(bindings.keys(Action::MoveDown), [Event::Char('j'), Event::Key(Key::Down)]);
+        assert_eq!(bindings.keys(Action::Lock), [Event::CtrlChar('l')]);
+
+        let err = toml::from_str::<KeyBindings>("copy = \"ctrl-y\"").unwrap_err();
+        assert!(err.to_string().contains("unknown action: copy"), "{}", err);
+    }
+
+    #[test]
+    fn valid_keys() {
+        assert_eq!(parse_key("j"), Ok(Event::Char('j')));
+        assert_eq!(parse_key("J"), Ok(Event::Char('J')));
+        assert_eq!(parse_key("ctrl-p"), Ok(Event::CtrlChar('p')));
+        assert_eq!(parse_key("Ctrl-P"), Ok(Event::CtrlChar('p')));
+        assert_eq!(parse_key("alt-X"), Ok(Event::AltChar('X')));
+        assert_eq!(parse_key("space"), Ok(Event::Char(' ')));
+        assert_eq!(parse_key("ESC"), Ok(Event::Key(Key::Esc)));
+        assert_eq!(parse_key("pagedown"), Ok(Event::Key(Key::PageDown)));
+        assert_eq!(parse_key("f1"), Ok(Event::Key(Key::F1)));
+        assert_eq!(parse_key("F12"), Ok(Event::Key(Key::F12)));
+    }
+
+    #[test]
+    fn invalid_keys() {
+        for key in ["", "ctrl-", "ctrl-ab", "alt-", "f0", "f13", "shift-a", "escape"] {
+            assert_eq!(parse_key(key), Err(format!("invalid key: {}", key)));
+        }
+    }
+
+    #[test]
+    fn default_keys_do_not_conflict() {
+        assert_eq!(KeyBindings::default().conflicts(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn keys_conflict_only_within_a_view() {
+        let conflicts = bindings(&[(Action::Lock, "j")]).conflicts();
+        assert_eq!(conflicts, ["j is bound to both Lock and MoveDown"]);
+
+        // Details and vault are separate views
+        assert!(bindings(&[(Action::ToggleHidden, "e")]).conflicts().is_empty());
+    }
+
+    #[test]
+    fn text_input_keys_in_text_fields() {
+        let conflicts = bindings(&[(Action::ClearInput, "x"), (Action::EndSearch, "left")])
+            .conflicts();
+
+        assert_eq!(
+            conflicts,
+            [
+                "x is bound to ClearInput, but text fields take it as input",
+                "<left> is bound to EndSearch, but text fields take it as input",
+            ]
+        );
+
+        assert!(bindings(&[(Action::ClearInput, "ctrl-x")]).conflicts().is_empty());
+    }
+}
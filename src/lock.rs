@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: MIT
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cursive::event::{Event, EventTrigger};
+use cursive::Cursive;
+
+use crate::vault::VaultData;
+use crate::{agent, clipboard, config, login};
+
+/// Drops all decrypted data, keys and tokens and goes back to the login dialog. The cached sync
+/// data is kept, so unlocking again works without network access.
+pub fn lock(siv: &mut Cursive) {
+    clipboard::clear();
+
+    if let Some(vault) = siv.user_data::<VaultData>() {
+        // Other accounts stay unlocked in the agent
+        agent::lock(vault.sync.profile.uuid);
+
+        vault.decrypted.clear();
+        vault.folders.clear();
+        vault.collections.clear();
+        vault.auth.lock();
+    }
+
+    // The item details enable auto-refresh for their TOTP countdown
+    siv.set_autorefresh(false);
+    login::create(siv);
+}
+
+/// Locks the vault after the configured time without any user input.
+pub fn start_idle_timer(siv: &mut Cursive) {
+    let timeout = config::get().lock_timeout;
+    if timeout == 0 {
+        return;
+    }
+
+    let timeout = Duration::from_secs(timeout);
+    let last_activity = Arc::new(Mutex::new(Instant::now()));
+
+    let activity = last_activity.clone();
+    let trigger = EventTrigger::from_fn(|event| {
+        !matches!(event, Event::Refresh | Event::WindowResize)
+    });
+
+    siv.set_on_pre_event(trigger, move |_| {
+        *activity.lock().unwrap() = Instant::now();
+    });
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+
+        let mut last_activity = last_activity.lock().unwrap();
+        if last_activity.elapsed() < timeout {
+            continue;
+        }
+
+        *last_activity = Instant::now();
+
+        let sent = sink.send(Box::new(|siv| {
+            if siv.user_data::<VaultData>().is_some_and(|vault| vault.is_unlocked()) {
+                lock(siv);
+            }
+        }));
+
+        // The UI is gone
+        if sent.is_err() {
+            break;
+        }
+    });
+}
@@ -3,32 +3,38 @@
 use std::thread;
 
 use cursive::direction::Orientation;
-use cursive::event::Event;
 use cursive::traits::*;
-use cursive::views::{Dialog, EditView, LinearLayout, OnEventView, TextContent, TextView};
+use cursive::views::{
+    Checkbox, Dialog, EditView, LinearLayout, OnEventView, SelectView, TextContent, TextView,
+};
 use cursive::{CbSink as CursiveSink, Cursive};
 
 use bitwarden::cipher::CipherSuite;
-use bitwarden::{self, ApiError, AuthData};
+use bitwarden::{self, ApiError, AuthData, ServerConfig, TwoFactorProvider, TwoFactorToken};
 
-use crate::vault::{self, VaultData};
+use crate::agent;
+use crate::error::Error;
+use crate::keys::{Action, OnAction};
+use crate::vault::{self, Account, VaultData};
 
-pub fn create(siv: &mut Cursive) {
-    let email_edit = EditView::new().with_name("email");
-    let email_view = OnEventView::new(email_edit).on_event(Event::CtrlChar('u'), |siv| {
-        siv.find_name::<EditView>("email").unwrap().set_content("")(siv);
-    });
-
-    let password_edit = EditView::new().secret().with_name("master_password");
-    let password_view = OnEventView::new(password_edit).on_event(Event::CtrlChar('u'), |siv| {
-        siv.find_name::<EditView>("master_password").unwrap().set_content("")(siv);
-    });
+fn input(name: &'static str, edit: EditView) -> impl View {
+    OnEventView::new(edit.with_name(name)).on_action(Action::ClearInput, move |siv| {
+        siv.find_name::<EditView>(name).unwrap().set_content("")(siv);
+    })
+}
 
+pub fn create(siv: &mut Cursive) {
     let layout = LinearLayout::new(Orientation::Vertical)
+        .child(TextView::new("server url (leave empty for bitwarden.com):"))
+        .child(input("server_url", EditView::new()))
+        .child(TextView::new("identity url (optional, overrides the server url):"))
+        .child(input("identity_url", EditView::new()))
+        .child(TextView::new("api url (optional, overrides the server url):"))
+        .child(input("api_url", EditView::new()))
         .child(TextView::new("email address:"))
-        .child(email_view)
+        .child(input("email", EditView::new()))
         .child(TextView::new("master password:"))
-        .child(password_view);
+        .child(input("master_password", EditView::new().secret()));
 
     let dialog = Dialog::around(layout)
         .title("bitwarden vault login")
@@ -38,13 +44,88 @@ pub fn create(siv: &mut Cursive) {
     siv.clear();
     siv.add_layer(dialog);
 
+    if let Some(server) = siv.user_data().map(|data: &mut VaultData| data.auth.server.clone()) {
+        fill_server(siv, &server);
+    }
+
     if let Some(email) = siv.user_data().map(|data: &mut VaultData| data.sync.profile.email.clone()) {
         siv.find_name::<EditView>("email").unwrap().set_content(email)(siv);
         siv.focus_name("master_password").unwrap();
+    } else {
+        siv.focus_name("email").unwrap();
     }
 }
 
+/// Fills in the server and email given on the command line, taking precedence over the cached
+/// ones.
+pub fn prefill(siv: &mut Cursive, server: Option<&ServerConfig>, email: Option<&str>) {
+    if let Some(server) = server {
+        fill_server(siv, server);
+    }
+
+    if let Some(email) = email {
+        siv.find_name::<EditView>("email").unwrap().set_content(email)(siv);
+        siv.focus_name("master_password").unwrap();
+    }
+}
+
+/// Fills in the base URL of the server if it follows the self-hosted layout, otherwise both of
+/// its URLs. The default server leaves all of them empty.
+fn fill_server(siv: &mut Cursive, server: &ServerConfig) {
+    let (base_url, identity_url, api_url) = match server.base_url() {
+        _ if *server == ServerConfig::default() => ("", "", ""),
+        Some(base_url) => (base_url, "", ""),
+        None => ("", server.identity_url.as_str(), server.api_url.as_str()),
+    };
+
+    siv.find_name::<EditView>("server_url").unwrap().set_content(base_url)(siv);
+    siv.find_name::<EditView>("identity_url").unwrap().set_content(identity_url)(siv);
+    siv.find_name::<EditView>("api_url").unwrap().set_content(api_url)(siv);
+}
+
+/// Decrypts the vault with the user key kept by the agent, if one is running and still unlocked.
+/// Returns `None` otherwise.
+pub fn unlock_with_agent(vault: &mut VaultData) -> Option<Result<(), Error>> {
+    let key = agent::fetch(vault.sync.profile.uuid)?;
+    vault.auth.cipher.set_user_key(key);
+
+    Some(vault::decrypt(vault))
+}
+
+/// Builds the server configuration from a base URL, with the identity and API URL taking
+/// precedence over the ones derived from it. Empty values are ignored.
+pub fn server_config(base_url: &str, identity_url: &str, api_url: &str) -> ServerConfig {
+    let base_url = base_url.trim();
+
+    let mut server = if base_url.is_empty() {
+        ServerConfig::default()
+    } else {
+        ServerConfig::from_base_url(base_url)
+    };
+
+    if !identity_url.trim().is_empty() {
+        server.identity_url = identity_url.trim().to_owned();
+    }
+
+    if !api_url.trim().is_empty() {
+        server.api_url = api_url.trim().to_owned();
+    }
+
+    server
+}
+
+fn entered_server(siv: &mut Cursive) -> ServerConfig {
+    let mut content = |name| siv.find_name::<EditView>(name).unwrap().get_content();
+
+    server_config(&content("server_url"), &content("identity_url"), &content("api_url"))
+}
+
 fn on_login(siv: &mut Cursive) {
+    login(siv, None);
+}
+
+fn login(siv: &mut Cursive, two_factor: Option<TwoFactorToken>) {
+    let server = entered_server(siv);
     let email = siv.find_name::<EditView>("email").unwrap().get_content().to_string();
     let password = siv.find_name::<EditView>("master_password").unwrap().get_content().to_string();
 
@@ -54,20 +135,34 @@ fn on_login(siv: &mut Cursive) {
     let progress_dialog = Dialog::around(progress_view).with_name("progress_dialog");
     siv.add_layer(progress_dialog);
 
-    let vault_data = siv.take_user_data();
+    // Cached data of another account is of no use, so use the data stored for the entered
+    // account, if any, or start over with a fresh sync
+    let account = Account { server: server.clone(), email: email.clone() };
+    let vault_data = siv
+        .take_user_data()
+        .filter(|data: &VaultData| Account::of(data) == account)
+        .or_else(|| vault::read_local_data(&account).ok());
+
     let sink = siv.cb_sink().clone();
     thread::spawn(move || {
         if let Some(data) = vault_data {
-            decrypt_cached(sink.clone(), progress_text, data, &email, &password);
+            decrypt_cached(sink.clone(), progress_text, data, &email, &password, two_factor);
         } else {
-            sync_and_decrypt(sink.clone(), progress_text, &email, &password);
+            sync_and_decrypt(
+                sink.clone(),
+                progress_text,
+                &server,
+                &email,
+                &password,
+                two_factor,
+                None,
+            );
         }
 
         sink.send(Box::new(|siv| {
             siv.set_autorefresh(false);
         })).unwrap();
     });
-
 }
 
 fn decrypt_cached(
@@ -75,36 +170,177 @@ fn decrypt_cached(
     progress: TextContent,
     mut vault: VaultData,
     email: &str,
-    master_password: &str
+    master_password: &str,
+    two_factor: Option<TwoFactorToken>,
 ) {
     progress.set_content("decrypting ...");
-    vault.auth.cipher = CipherSuite::from(email, master_password, vault.auth.kdf_iterations);
+    let cipher = match CipherSuite::from(email, master_password, vault.auth.kdf) {
+        Ok(cipher) => cipher,
+        Err(err) => return handle_login_error(sink, Some(vault), err.into()),
+    };
+    vault.auth.cipher = cipher;
 
     if vault.auth.cipher.set_decrypt_key(&vault.sync.profile.key).is_err() {
-        handle_login_error(sink, Some(vault), ApiError::LoginFailed);
+        handle_login_error(sink, Some(vault), ApiError::LoginFailed.into());
     } else {
-        vault::decrypt(&mut vault);
+        let result = vault::decrypt(&mut vault);
 
-        sink.send(Box::new(|siv| {
-            siv.set_user_data(vault);
-            vault::create(siv);
-        })).unwrap();
+        if vault.auth.has_session() {
+            open_vault(sink, vault, result);
+        } else {
+            // The server rejected the stored tokens (or they could not be decrypted), so start
+            // a new session using the master password
+            let server = vault.auth.server.clone();
+            let cached = Some(vault);
+            sync_and_decrypt(sink, progress, &server, email, master_password, two_factor, cached);
+        }
     }
 }
 
-fn sync_and_decrypt(sink: CursiveSink, progress: TextContent, email: &str, master_password: &str) {
-    match bitwarden::authenticate(email, master_password) {
-        Ok(auth) => {
+/// Shows the decrypted vault, after storing it.
+fn open_vault(sink: CursiveSink, mut vault: VaultData, result: Result<(), Error>) {
+    if let Some(key) = vault.auth.cipher.key_for(None) {
+        agent::store(vault.sync.profile.uuid, key);
+    }
+
+    // Storing freshly synced data requires the user key to encrypt the tokens. Data stored
+    // by older versions gets its plaintext tokens encrypted this way as well.
+    let result = result.and(vault::save_local_data(&mut vault));
+
+    sink.send(Box::new(|siv| {
+        siv.set_user_data(vault);
+        vault::create(siv);
+
+        if let Err(err) = result {
+            siv.add_layer(Dialog::info(err.to_string()));
+        }
+    })).unwrap();
+}
+
+/// Logs in and decrypts the freshly synced data. The already decrypted `cached` data, if any, is
+/// shown instead if the server cannot be reached.
+fn sync_and_decrypt(
+    sink: CursiveSink,
+    progress: TextContent,
+    server: &ServerConfig,
+    email: &str,
+    master_password: &str,
+    two_factor: Option<TwoFactorToken>,
+    cached: Option<VaultData>,
+) {
+    let mut device = vault::read_device().unwrap_or_default();
+    let result = bitwarden::authenticate(
+        server,
+        &mut device,
+        email,
+        master_password,
+        two_factor.as_ref(),
+    );
+
+    // Failing to persist the device only means having to enter the second factor again
+    let _ = vault::save_device(&device);
+
+    match (result, cached) {
+        (Ok(auth), _) => {
             progress.set_content("syncing ...");
-            let vault = sync_vault_data(auth).unwrap();
 
-            decrypt_cached(sink, progress, vault, email, master_password);
+            match sync_vault_data(auth) {
+                Ok(vault) => decrypt_cached(sink, progress, vault, email, master_password, None),
+                Err(err) => handle_login_error(sink, None, err.into()),
+            }
         },
-        Err(err) => handle_login_error(sink, None, err),
+        (Err(ApiError::TwoFactorRequired(providers)), _) => request_two_factor(sink, providers),
+        (Err(err @ ApiError::Network(_)), Some(vault)) => open_vault(sink, vault, Err(err.into())),
+        (Err(err), _) => handle_login_error(sink, None, err.into()),
     }
 }
 
-fn handle_login_error(sink: CursiveSink, vault: Option<VaultData>, error: ApiError) {
+fn request_two_factor(sink: CursiveSink, providers: Vec<TwoFactorProvider>) {
+    sink.send(Box::new(move |siv| {
+        siv.pop_layer();
+
+        let supported: Vec<_> = providers.iter().copied().filter(|p| p.is_supported()).collect();
+
+        match supported[..] {
+            [] => {
+                let enabled: Vec<_> = providers.iter().map(|p| p.to_string()).collect();
+                siv.add_layer(Dialog::info(format!(
+                    "none of the enabled two-factor methods are supported: {}",
+                    enabled.join(", "),
+                )));
+            },
+            // The server sends the email on its own if it is the only option
+            [provider] => show_two_factor_prompt(siv, provider),
+            _ => {
+                let select = SelectView::new()
+                    .with_all(supported.into_iter().map(|p| (p.to_string(), p)))
+                    .on_submit(|siv, provider: &TwoFactorProvider| {
+                        siv.pop_layer();
+
+                        if *provider == TwoFactorProvider::Email {
+                            send_email_code(siv);
+                        }
+
+                        show_two_factor_prompt(siv, *provider);
+                    });
+
+                siv.add_layer(
+                    Dialog::around(select)
+                        .title("choose two-factor method")
+                        .dismiss_button("Cancel"),
+                );
+            },
+        }
+    })).unwrap();
+}
+
+fn send_email_code(siv: &mut Cursive) {
+    let server = entered_server(siv);
+    let email = siv.find_name::<EditView>("email").unwrap().get_content().to_string();
+    let password = siv.find_name::<EditView>("master_password").unwrap().get_content().to_string();
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        if let Err(err) = bitwarden::send_email_code(&server, &email, &password) {
+            sink.send(Box::new(move |siv| {
+                siv.add_layer(Dialog::info(err.to_string()));
+            })).unwrap();
+        }
+    });
+}
+
+fn show_two_factor_prompt(siv: &mut Cursive, provider: TwoFactorProvider) {
+    let code_edit = EditView::new()
+        .on_submit(move |siv, _| submit_two_factor(siv, provider))
+        .with_name("two_factor_code");
+
+    let layout = LinearLayout::new(Orientation::Vertical)
+        .child(TextView::new(format!("{} code:", provider)))
+        .child(code_edit)
+        .child(
+            LinearLayout::horizontal()
+                .child(Checkbox::new().with_name("two_factor_remember"))
+                .child(TextView::new(" remember this device")),
+        );
+
+    let dialog = Dialog::around(layout)
+        .title("two-factor authentication")
+        .button("Ok", move |siv| submit_two_factor(siv, provider))
+        .dismiss_button("Cancel")
+        .min_width(40);
+
+    siv.add_layer(dialog);
+}
+
+fn submit_two_factor(siv: &mut Cursive, provider: TwoFactorProvider) {
+    let token = siv.find_name::<EditView>("two_factor_code").unwrap().get_content().trim().to_owned();
+    let remember = siv.find_name::<Checkbox>("two_factor_remember").unwrap().is_checked();
+
+    siv.pop_layer();
+    login(siv, Some(TwoFactorToken { provider, token, remember }));
+}
+
+fn handle_login_error(sink: CursiveSink, vault: Option<VaultData>, error: Error) {
     sink.send(Box::new(move |siv| {
         siv.pop_layer();
         siv.find_name::<EditView>("master_password").unwrap().set_content("")(siv);
@@ -117,9 +353,7 @@ fn handle_login_error(sink: CursiveSink, vault: Option<VaultData>, error: ApiErr
     })).unwrap();
 }
 
-fn sync_vault_data(auth: AuthData) -> Result<VaultData, String> {
-    bitwarden::sync(&auth)
-        .map(|sync| VaultData { auth, sync, decrypted: Vec::new() })
-        .map_err(|e| e.to_string())
-        .and_then(|vault| vault::save_local_data(&vault).and(Ok(vault)))
+/// Syncs the vault data after logging in. It is stored only once unlocked, see `decrypt_cached`.
+pub fn sync_vault_data(mut auth: AuthData) -> Result<VaultData, ApiError> {
+    bitwarden::sync(&mut auth).map(|sync| VaultData::new(auth, sync))
 }
@@ -1,15 +1,63 @@
 // SPDX-License-Identifier: MIT
 
+use std::process;
+
+use clap::Parser;
+use cursive::views::Dialog;
+
+mod accounts;
+mod agent;
+mod cli;
+mod clipboard;
+mod config;
+mod details;
+mod edit;
+mod error;
+mod keys;
+mod lock;
 mod login;
 mod vault;
 
 fn main() {
+    let args = cli::Args::parse();
+    let config_result = config::load(args.config.as_deref());
+
+    if let Some(dir) = &args.data_dir {
+        vault::set_data_dir(dir.clone());
+    }
+
+    if let Some(command) = &args.command {
+        if let Err(err) = config_result.and_then(|_| cli::run(&args, command)) {
+            eprintln!("bwtui: {}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
     let mut siv = cursive::default();
 
-    if let Ok(vault) = vault::read_local_data() {
-        siv.set_user_data(vault);
+    keys::bind_quit(&mut siv);
+
+    let accounts = cli::selected_accounts(&args);
+
+    match &accounts[..] {
+        [] => {
+            login::create(&mut siv);
+            let server = cli::server_config(&args);
+            login::prefill(&mut siv, server.as_ref(), args.email.as_deref());
+        }
+        [account] => accounts::open(&mut siv, account),
+        _ => accounts::create(&mut siv, accounts),
+    }
+
+    lock::start_idle_timer(&mut siv);
+
+    if let Err(err) = config_result.and_then(|_| config::check_keys()) {
+        siv.add_layer(Dialog::info(err.to_string()));
     }
 
-    login::create(&mut siv);
     siv.run();
+
+    clipboard::clear();
 }
@@ -1,47 +1,220 @@
 // SPDX-License-Identifier: MIT
 
 use std::cmp::Ordering;
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter};
+use std::ops::Deref;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::thread;
 
-use clipboard::ClipboardContext;
-use clipboard::ClipboardProvider;
-use cursive::event::{Event, Key};
+use chrono::{DateTime, Utc};
 use cursive::traits::*;
-use cursive::views::{Dialog, DummyView, EditView, LinearLayout, OnEventView, TextView};
+use cursive::views::{
+    BoxedView, Dialog, DummyView, EditView, HideableView, LinearLayout, OnEventView, Panel,
+    SelectView, TextView,
+};
 use cursive::Cursive;
 use cursive_table_view::{TableView, TableViewItem};
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use unicase::UniCase;
+use uuid::Uuid;
 
-use bitwarden::cipher::CipherSuite;
-use bitwarden::{AuthData, CipherEntry, SyncResponse};
+use bitwarden::cipher::{CipherError, CipherSuite, Decrypt, SecretString};
+use bitwarden::totp::Totp;
+use bitwarden::{
+    AuthData, CipherEntry, CipherEntryFields, CipherEntryHistory, CipherType, Device, ItemData,
+    ServerConfig, SyncResponse,
+};
+
+use crate::error::Error;
+use crate::keys::{Action, OnAction};
+use crate::{accounts, clipboard, config, details, edit, lock};
+
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+const ACCOUNTS_DIR: &str = "accounts";
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
 enum VaultColumn {
     Favorite,
+    Type,
     Name,
-    Username,
+    Details,
 }
 
-#[derive(Clone)]
 pub struct VaultEntry {
-    name: UniCase<String>,
-    username: UniCase<String>,
-    password: String,
+    pub uuid: Uuid,
+    pub folder: Option<Uuid>,
+    collections: Vec<Uuid>,
+    pub type_: CipherType,
+    pub name: UniCase<String>,
+    pub details: UniCase<String>,
     favorite: String,
+    pub data: ItemData,
+    pub notes: Option<SecretString>,
+    pub fields: Vec<CipherEntryFields<SecretString>>,
+    pub password_history: Vec<CipherEntryHistory<SecretString>>,
+    pub last_changed: DateTime<Utc>,
+}
+
+/// Row of the vault table. Cloning it, like on every search, only clones a reference to the
+/// decrypted entry instead of its plaintexts.
+#[derive(Clone)]
+pub struct VaultRow(Arc<VaultEntry>);
+
+impl Deref for VaultRow {
+    type Target = VaultEntry;
+
+    fn deref(&self) -> &VaultEntry {
+        &self.0
+    }
+}
+
+pub struct VaultFolder {
+    uuid: Uuid,
+    name: String,
+}
+
+pub struct VaultCollection {
+    uuid: Uuid,
+    name: String,
+}
+
+/// Restricts the vault table to the items of a folder.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FolderFilter {
+    All,
+    Unfiled,
+    Folder(Uuid),
+}
+
+impl FolderFilter {
+    fn matches(self, entry: &VaultEntry) -> bool {
+        match self {
+            Self::All => true,
+            Self::Unfiled => entry.folder.is_none(),
+            Self::Folder(uuid) => entry.folder == Some(uuid),
+        }
+    }
+}
+
+/// Restricts the vault table to the items of an organization collection.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CollectionFilter {
+    All,
+    Collection(Uuid),
+}
+
+impl CollectionFilter {
+    fn matches(self, entry: &VaultEntry) -> bool {
+        match self {
+            Self::All => true,
+            Self::Collection(uuid) => entry.collections.contains(&uuid),
+        }
+    }
+}
+
+/// Account with stored data, identified by its server and email address.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Account {
+    pub server: ServerConfig,
+    pub email: String,
+}
+
+impl Account {
+    pub fn of(vault: &VaultData) -> Self {
+        Self {
+            server: vault.auth.server.clone(),
+            email: vault.sync.profile.email.clone(),
+        }
+    }
+
+    /// Whether this is the account on the given server and with the given email, if any.
+    pub fn matches(&self, server: Option<&ServerConfig>, email: Option<&str>) -> bool {
+        server.map_or(true, |server| self.server == *server)
+            && email.map_or(true, |email| self.email.eq_ignore_ascii_case(email))
+    }
+
+    fn server_name(&self) -> &str {
+        if self.server == ServerConfig::default() {
+            "bitwarden.com"
+        } else {
+            let url = self.server.base_url().unwrap_or(&self.server.identity_url);
+            url.split_once("://").map_or(url, |(_, rest)| rest)
+        }
+    }
+
+    /// Name of the directory the data of the account is stored in, like
+    /// `user@example.com_vault.example.com_0123456789abcdef`. The readable part may be the same
+    /// for different accounts, so it is followed by a hash of the email and both server URLs.
+    fn dir_name(&self) -> String {
+        let email = self.email.to_ascii_lowercase();
+
+        let mut hasher = Sha256::new();
+        for part in [&email, &self.server.identity_url, &self.server.api_url] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+
+        let hash: String = hasher.finalize()[..8].iter().map(|b| format!("{:02x}", b)).collect();
+
+        format!("{}_{}_{}", email, self.server_name(), hash)
+            .chars()
+            .map(|c| if c.is_alphanumeric() || "@.-".contains(c) { c } else { '_' })
+            .collect()
+    }
+}
+
+impl PartialEq for Account {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches(Some(&other.server), Some(&other.email))
+    }
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.email, self.server_name())
+    }
 }
 
 pub struct VaultData {
     pub auth: AuthData,
     pub sync: SyncResponse,
-    pub decrypted: Vec<VaultEntry>,
+    pub decrypted: Vec<VaultRow>,
+    pub folders: Vec<VaultFolder>,
+    pub folder_filter: FolderFilter,
+    pub collections: Vec<VaultCollection>,
+    pub collection_filter: CollectionFilter,
+}
+
+impl VaultData {
+    pub fn new(auth: AuthData, sync: SyncResponse) -> Self {
+        Self {
+            auth,
+            sync,
+            decrypted: Vec::new(),
+            folders: Vec::new(),
+            folder_filter: FolderFilter::All,
+            collections: Vec::new(),
+            collection_filter: CollectionFilter::All,
+        }
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.auth.cipher.key_for(None).is_some()
+    }
 }
 
-type VaultTableView = TableView<VaultEntry, VaultColumn>;
+type VaultTableView = TableView<VaultRow, VaultColumn>;
+type FolderListView = SelectView<FolderFilter>;
+type CollectionListView = SelectView<CollectionFilter>;
 
 impl VaultEntry {
     pub fn from_cipher_entry(entry: &CipherEntry, cipher: &CipherSuite) -> Option<VaultEntry> {
@@ -51,21 +224,81 @@ impl VaultEntry {
             "\u{2606}"
         };
 
+        let key = entry.key(cipher)?;
+        let key = key.as_ref();
+        let data = entry.decrypt_data(key)?;
+
         Some(Self {
-            name: UniCase::new(entry.name.decrypt(cipher)?),
-            username: UniCase::new(entry.data.username.decrypt(cipher)?),
-            password: entry.data.password.decrypt(cipher)?,
+            uuid: entry.uuid,
+            folder: entry.folder_id,
+            collections: entry.collection_ids.clone(),
+            type_: entry.cipher_type()?,
+            name: UniCase::new(entry.name.decrypt(key)?.to_string()),
+            details: UniCase::new(describe(&data)),
             favorite: favorite.to_owned(),
+            data,
+            notes: entry.notes.decrypt(key)?,
+            fields: entry.fields.decrypt(key)?.unwrap_or_default(),
+            password_history: entry.password_history.decrypt(key)?.unwrap_or_default(),
+            last_changed: entry.last_changed,
         })
     }
+
+    pub fn username(&self) -> Option<&str> {
+        match &self.data {
+            ItemData::Login(login) => login.username.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        match &self.data {
+            ItemData::Login(login) => login.password.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn totp(&self) -> Option<&str> {
+        match &self.data {
+            ItemData::Login(login) => login.totp.as_deref().filter(|totp| !totp.is_empty()),
+            _ => None,
+        }
+    }
+}
+
+/// Short description of an item shown next to its name, like the username of a login.
+fn describe(data: &ItemData) -> String {
+    let parts = match data {
+        ItemData::Login(login) => vec![login.username.as_deref()],
+        ItemData::SecureNote => Vec::new(),
+        ItemData::Card(card) => {
+            let last_digits = card.number.as_deref().map(|number| {
+                let start = number.char_indices().rev().nth(3).map_or(0, |(i, _)| i);
+                &number[start..]
+            });
+
+            vec![card.brand.as_deref(), last_digits]
+        }
+        ItemData::Identity(identity) => {
+            vec![identity.first_name.as_deref(), identity.last_name.as_deref()]
+        }
+    };
+
+    parts
+        .into_iter()
+        .flatten()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-impl TableViewItem<VaultColumn> for VaultEntry {
+impl TableViewItem<VaultColumn> for VaultRow {
     fn to_column(&self, column: VaultColumn) -> String {
         match column {
             VaultColumn::Favorite => self.favorite.clone(),
+            VaultColumn::Type => self.type_.to_string(),
             VaultColumn::Name => self.name.to_string(),
-            VaultColumn::Username => self.username.to_string(),
+            VaultColumn::Details => self.details.to_string(),
         }
     }
 
@@ -75,8 +308,9 @@ impl TableViewItem<VaultColumn> for VaultEntry {
     {
         match column {
             VaultColumn::Favorite => self.favorite.cmp(&other.favorite),
+            VaultColumn::Type => (self.type_ as u8).cmp(&(other.type_ as u8)),
             VaultColumn::Name => self.name.cmp(&other.name),
-            VaultColumn::Username => self.username.cmp(&other.username),
+            VaultColumn::Details => self.details.cmp(&other.details),
         }
     }
 }
@@ -84,15 +318,14 @@ impl TableViewItem<VaultColumn> for VaultEntry {
 pub fn create(siv: &mut Cursive) {
     let mut table = VaultTableView::new()
         .column(VaultColumn::Favorite, "", |c| c.width(1))
+        .column(VaultColumn::Type, "Type", |c| c.width(8))
         .column(VaultColumn::Name, "Name", |c| c.width_percent(25))
-        .column(VaultColumn::Username, "Username", |c| c)
-        .items(siv.user_data::<VaultData>().unwrap().decrypted.clone());
+        .column(VaultColumn::Details, "Details", |c| c);
 
-    table.sort_by(VaultColumn::Name, Ordering::Less);
-    table.sort_by(VaultColumn::Favorite, Ordering::Less);
+    table.set_on_submit(|siv, _, index| show_details(siv, index));
 
     let table_view = OnEventView::new(table.with_name("password_table").full_screen())
-        .on_event('j', |siv| {
+        .on_action(Action::MoveDown, |siv| {
             siv.call_on_name("password_table", |view: &mut VaultTableView| {
                 if let Some(row) = view.row() {
                     if row < view.len() - 1 {
@@ -102,7 +335,7 @@ pub fn create(siv: &mut Cursive) {
             })
             .unwrap();
         })
-        .on_event('k', |siv| {
+        .on_action(Action::MoveUp, |siv| {
             siv.call_on_name("password_table", |view: &mut VaultTableView| {
                 if let Some(row) = view.row() {
                     if row > 0 {
@@ -112,44 +345,58 @@ pub fn create(siv: &mut Cursive) {
             })
             .unwrap();
         })
-        .on_event('J', |siv| {
+        .on_action(Action::MoveLast, |siv| {
             siv.call_on_name("password_table", |view: &mut VaultTableView| {
-                view.set_selected_row(view.len() - 1);
+                // The filters might leave the table empty
+                if !view.is_empty() {
+                    view.set_selected_row(view.len() - 1);
+                }
             })
             .unwrap();
         })
-        .on_event('K', |siv| {
+        .on_action(Action::MoveFirst, |siv| {
             siv.call_on_name("password_table", |view: &mut VaultTableView| {
                 view.set_selected_row(0);
             })
             .unwrap();
         })
-        .on_event(Event::CtrlChar('u'), |siv| {
-            siv.call_on_name("password_table", |view: &mut VaultTableView| {
-                if let Some(row) = view.item() {
-                    if let Some(entry) = view.borrow_item(row) {
-                        let mut clipboard: ClipboardContext = ClipboardProvider::new().unwrap();
-
-                        clipboard.set_contents(entry.username.to_string()).unwrap();
-                    }
-                }
-            })
-            .unwrap();
+        .on_action(Action::CopyUsername, |siv| {
+            if let Some(username) = selected_value(siv, VaultEntry::username) {
+                clipboard::copy(siv, &username);
+            }
         })
-        .on_event(Event::CtrlChar('p'), |siv| {
-            siv.call_on_name("password_table", |view: &mut VaultTableView| {
-                if let Some(row) = view.item() {
-                    if let Some(entry) = view.borrow_item(row) {
-                        let mut clipboard: ClipboardContext = ClipboardProvider::new().unwrap();
-
-                        clipboard.set_contents(entry.password.clone()).unwrap();
-                    }
-                }
-            })
-            .unwrap();
+        .on_action(Action::CopyPassword, |siv| {
+            if let Some(password) = selected_value(siv, VaultEntry::password) {
+                clipboard::copy(siv, &password);
+            }
         })
-        .on_event(Event::CtrlChar('f'), |siv| {
+        .on_action(Action::CopyTotp, copy_totp)
+        .on_action(Action::Search, |siv| {
             siv.focus_name("search_field").unwrap();
+        })
+        .on_action(Action::Sync, resync)
+        .on_action(Action::Lock, lock::lock)
+        .on_action(Action::SwitchAccount, accounts::switch)
+        .on_action(Action::NewItem, edit::create_new)
+        .on_action(Action::EditItem, |siv| {
+            let entry = {
+                let table = siv.find_name::<VaultTableView>("password_table").unwrap();
+                table.item().and_then(|index| table.borrow_item(index).cloned())
+            };
+
+            if let Some(entry) = entry {
+                edit::create(siv, &entry);
+            }
+        })
+        .on_action(Action::ShowFilters, |siv| {
+            siv.focus_name("folder_list").unwrap();
+        })
+        .on_action(Action::ShowDetails, |siv| {
+            let index = siv.find_name::<VaultTableView>("password_table").unwrap().item();
+
+            if let Some(index) = index {
+                show_details(siv, index);
+            }
         });
 
     let search_field = EditView::new()
@@ -163,26 +410,68 @@ pub fn create(siv: &mut Cursive) {
         .child(TextView::new("search: "))
         .child(
             OnEventView::new(search_field)
-                .on_event(Event::CtrlChar('f'), |siv| {
-                    siv.focus_name("password_table").unwrap();
-                })
-                .on_event(Key::Esc, |siv| {
+                .on_action(Action::Search, |siv| {
                     siv.focus_name("password_table").unwrap();
                 })
-                .on_event(Key::Enter, |siv| {
+                .on_action(Action::EndSearch, |siv| {
                     siv.focus_name("password_table").unwrap();
                 })
-                .on_event(Event::CtrlChar('u'), |siv| {
+                .on_action(Action::ClearInput, |siv| {
                     if let Some(mut view) = siv.find_name::<EditView>("search_field") {
                         view.set_content("")(siv);
                     }
                 }),
         );
 
-    let main_view = LinearLayout::vertical()
-        .child(search_view)
-        .child(DummyView)
-        .child(table_view);
+    let mut folder_list = FolderListView::new().on_select(|siv, filter| {
+        siv.user_data::<VaultData>().unwrap().folder_filter = *filter;
+        refresh_table(siv);
+    });
+    populate_folders(&mut folder_list, siv.user_data::<VaultData>().unwrap());
+
+    let mut collection_list = CollectionListView::new().on_select(|siv, filter| {
+        siv.user_data::<VaultData>().unwrap().collection_filter = *filter;
+        refresh_table(siv);
+    });
+    populate_collections(&mut collection_list, siv.user_data::<VaultData>().unwrap());
+
+    let mut collection_panel = HideableView::new(
+        Panel::new(BoxedView::boxed(sidebar_list(collection_list, "collection_list")))
+            .title("collections"),
+    );
+    collection_panel.set_visible(!siv.user_data::<VaultData>().unwrap().collections.is_empty());
+
+    let sidebar = LinearLayout::vertical()
+        .child(Panel::new(sidebar_list(folder_list, "folder_list")).title("folders"))
+        .child(collection_panel.with_name("collection_panel"))
+        .max_width(30);
+
+    let main_view = LinearLayout::horizontal()
+        .child(sidebar)
+        .child(
+            LinearLayout::vertical()
+                .child(search_view)
+                .child(DummyView)
+                .child(table_view),
+        );
+
+    let keys = &config::get().keys;
+    let actions_help = keys.help(&[
+        (Action::Quit, "Quit"),
+        (Action::Lock, "Lock"),
+        (Action::CopyUsername, "Copy username"),
+        (Action::CopyPassword, "Copy password"),
+        (Action::CopyTotp, "Copy TOTP"),
+        (Action::NewItem, "New"),
+        (Action::EditItem, "Edit"),
+        (Action::Sync, "Sync"),
+    ]);
+    let navigation_help = keys.help(&[
+        (Action::ShowFilters, "Filters"),
+        (Action::ShowDetails, "Details"),
+        (Action::SwitchAccount, "Accounts"),
+        (Action::Search, "fuzzy-search"),
+    ]);
 
     let layout = LinearLayout::vertical()
         .child(
@@ -192,37 +481,284 @@ pub fn create(siv: &mut Cursive) {
         )
         .child(
             LinearLayout::horizontal()
-                .child(TextView::new("^C: Quit  ^U: Copy username  ^P: Copy password").full_width())
-                .child(TextView::new("^F: fuzzy-search")),
+                .child(TextView::new(actions_help).full_width())
+                .child(TextView::empty().with_name("clipboard_status"))
+                .child(DummyView.fixed_width(2))
+                .child(TextView::new(navigation_help)),
         );
 
     siv.clear();
     siv.add_fullscreen_layer(layout);
     siv.focus_name("password_table").unwrap();
+
+    // Fills the table, restricted to the folder and collection selected before locking
+    refresh_table(siv);
+}
+
+/// Wraps a sidebar filter list with the usual movement keys, going back to the table on
+/// `l`/`<enter>`.
+fn sidebar_list<T: 'static>(list: SelectView<T>, name: &'static str) -> impl View {
+    OnEventView::new(list.with_name(name).scrollable())
+        .on_action(Action::MoveDown, move |siv| {
+            let callback = siv.find_name::<SelectView<T>>(name).unwrap().select_down(1);
+            callback(siv);
+        })
+        .on_action(Action::MoveUp, move |siv| {
+            let callback = siv.find_name::<SelectView<T>>(name).unwrap().select_up(1);
+            callback(siv);
+        })
+        .on_action(Action::FocusVault, |siv| {
+            siv.focus_name("password_table").unwrap();
+        })
 }
 
-pub fn decrypt(vault: &mut VaultData) {
-    vault.decrypted = vault.sync
+/// Decrypts the vault with the keys of the cipher suite. Entries which cannot be decrypted are
+/// left out and reported afterwards, the rest of the vault is usable nevertheless.
+pub fn decrypt(vault: &mut VaultData) -> Result<(), Error> {
+    // Tokens are only needed for syncing, so the vault is decrypted nevertheless. Without them,
+    // logging in again starts a new session.
+    let unsealed = vault.auth.unseal();
+
+    // Organization memberships might have changed with the last sync. Items of organizations
+    // whose key is not available cannot be decrypted below and are reported along with them.
+    let profile = &vault.sync.profile;
+    let skipped_organizations = match profile.unlock_organizations(&mut vault.auth.cipher) {
+        Ok(skipped) => skipped.len(),
+        // Without the private key, none of the organization keys can be unwrapped
+        Err(_) => profile.organizations.iter().filter(|org| org.key.is_some()).count(),
+    };
+
+    let cipher = &vault.auth.cipher;
+    let user_key = cipher
+        .key_for(None)
+        .ok_or_else(|| CipherError::DecryptionKeyError("user key not available".to_owned()))?;
+
+    let ciphers: Vec<_> = vault.sync
         .ciphers
         .iter()
-        .map(|c| VaultEntry::from_cipher_entry(c, &vault.auth.cipher).unwrap())
+        // Skip item types introduced after this client was written
+        .filter(|c| c.cipher_type().is_some())
+        .collect();
+
+    vault.decrypted = ciphers
+        .iter()
+        .filter_map(|c| VaultEntry::from_cipher_entry(c, cipher))
+        .map(|entry| VaultRow(Arc::new(entry)))
         .collect();
+
+    vault.folders = vault.sync
+        .folders
+        .iter()
+        .filter_map(|f| {
+            let name = f.name.decrypt(user_key)?;
+            Some(VaultFolder { uuid: f.uuid, name: name.to_string() })
+        })
+        .collect();
+
+    vault.collections = vault.sync
+        .collections
+        .iter()
+        .filter_map(|c| {
+            let name = c.name.decrypt(cipher.key_for(Some(c.organization_id))?)?;
+            Some(VaultCollection { uuid: c.uuid, name: name.to_string() })
+        })
+        .collect();
+
+    let failed = ciphers.len() - vault.decrypted.len()
+        + vault.sync.folders.len() - vault.folders.len()
+        + vault.sync.collections.len() - vault.collections.len()
+        + skipped_organizations;
+
+    vault.folders.sort_by(|a, b| UniCase::new(&a.name).cmp(&UniCase::new(&b.name)));
+
+    // The selected folder might have been deleted on the server in the meantime
+    if let FolderFilter::Folder(uuid) = vault.folder_filter {
+        if !vault.folders.iter().any(|f| f.uuid == uuid) {
+            vault.folder_filter = FolderFilter::All;
+        }
+    }
+
+    vault.collections.sort_by(|a, b| UniCase::new(&a.name).cmp(&UniCase::new(&b.name)));
+
+    if let CollectionFilter::Collection(uuid) = vault.collection_filter {
+        if !vault.collections.iter().any(|c| c.uuid == uuid) {
+            vault.collection_filter = CollectionFilter::All;
+        }
+    }
+
+    unsealed.map_err(Error::Tokens)?;
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(Error::UndecryptableEntries(failed)),
+    }
+}
+
+/// Fills the folder sidebar. Nested folders use `/` in their name, like in the official clients,
+/// and are shown indented below their parent.
+fn populate_folders(view: &mut FolderListView, vault: &VaultData) {
+    let names: HashSet<&str> = vault.folders.iter().map(|f| f.name.as_str()).collect();
+
+    view.clear();
+    view.add_item("All items", FolderFilter::All);
+
+    for folder in &vault.folders {
+        let (depth, label) = folder_label(&folder.name, &names);
+        let label = format!("{}{}", "  ".repeat(depth), label);
+        view.add_item(label, FolderFilter::Folder(folder.uuid));
+    }
+
+    view.add_item("No folder", FolderFilter::Unfiled);
+
+    let selected = view.iter().position(|(_, filter)| *filter == vault.folder_filter);
+    view.set_selection(selected.unwrap_or(0));
+}
+
+/// Fills the collection sidebar. Collections are nested the same way as folders.
+fn populate_collections(view: &mut CollectionListView, vault: &VaultData) {
+    let names: HashSet<&str> = vault.collections.iter().map(|c| c.name.as_str()).collect();
+
+    view.clear();
+    view.add_item("All collections", CollectionFilter::All);
+
+    for collection in &vault.collections {
+        let (depth, label) = folder_label(&collection.name, &names);
+        let label = format!("{}{}", "  ".repeat(depth), label);
+        view.add_item(label, CollectionFilter::Collection(collection.uuid));
+    }
+
+    let selected = view.iter().position(|(_, filter)| *filter == vault.collection_filter);
+    view.set_selection(selected.unwrap_or(0));
+}
+
+/// Returns the nesting depth of a folder and the part of its name below its closest parent.
+fn folder_label<'a>(name: &'a str, names: &HashSet<&str>) -> (usize, &'a str) {
+    for (pos, _) in name.rmatch_indices('/') {
+        if names.contains(&name[..pos]) {
+            let (depth, _) = folder_label(&name[..pos], names);
+            return (depth + 1, &name[pos + 1..]);
+        }
+    }
+
+    (0, name)
+}
+
+/// Returns a value of the selected entry.
+fn selected_value<F>(siv: &mut Cursive, value: F) -> Option<SecretString>
+where
+    F: Fn(&VaultEntry) -> Option<&str>,
+{
+    siv.call_on_name("password_table", |view: &mut VaultTableView| {
+        view.item()
+            .and_then(|row| view.borrow_item(row))
+            .and_then(|entry| value(entry).map(SecretString::from))
+    })
+    .flatten()
+}
+
+fn copy_totp(siv: &mut Cursive) {
+    if let Some(key) = selected_value(siv, VaultEntry::totp) {
+        match Totp::parse(&key) {
+            Ok(totp) => clipboard::copy(siv, &totp.now().0),
+            Err(err) => {
+                siv.add_layer(Dialog::info(err.to_string()));
+            }
+        }
+    }
+}
+
+fn show_details(siv: &mut Cursive, index: usize) {
+    let entry = siv
+        .find_name::<VaultTableView>("password_table")
+        .unwrap()
+        .borrow_item(index)
+        .cloned();
+
+    if let Some(entry) = entry {
+        details::create(siv, &entry);
+    }
+}
+
+fn resync(siv: &mut Cursive) {
+    let mut vault = match siv.take_user_data::<VaultData>() {
+        Some(vault) => vault,
+        None => return,
+    };
+
+    siv.set_autorefresh(true);
+    siv.add_layer(Dialog::around(TextView::new("syncing ...")));
+
+    let sink = siv.cb_sink().clone();
+    thread::spawn(move || {
+        let mut result = bitwarden::sync(&mut vault.auth)
+            .map(|sync| vault.sync = sync)
+            .map_err(Error::from);
+
+        sink.send(Box::new(move |siv| {
+            siv.pop_layer();
+            siv.set_autorefresh(false);
+
+            if result.is_ok() {
+                result = decrypt(&mut vault);
+                populate_folders(&mut siv.find_name("folder_list").unwrap(), &vault);
+                populate_collections(&mut siv.find_name("collection_list").unwrap(), &vault);
+
+                siv.call_on_name("collection_panel", |view: &mut HideableView<Panel<BoxedView>>| {
+                    view.set_visible(!vault.collections.is_empty());
+                });
+            }
+
+            // The access token might have been refreshed even if the sync itself failed
+            let saved = save_local_data(&mut vault);
+            let result = result.and(saved);
+
+            siv.set_user_data(vault);
+            refresh_table(siv);
+
+            if let Err(err) = result {
+                siv.add_layer(Dialog::info(err.to_string()));
+            }
+        })).unwrap();
+    });
+}
+
+/// Re-populates the table from the decrypted entries, keeping the search term and selection.
+pub fn refresh_table(siv: &mut Cursive) {
+    let selected = siv.call_on_name("password_table", |view: &mut VaultTableView| {
+        view.item().and_then(|index| view.borrow_item(index)).map(|entry| entry.uuid)
+    }).flatten();
+
+    let search_term = siv.find_name::<EditView>("search_field").unwrap().get_content();
+    fuzzy_match_on_edit(siv, &search_term);
+
+    if let Some(uuid) = selected {
+        siv.call_on_name("password_table", |view: &mut VaultTableView| {
+            if let Some(index) = view.borrow_items().iter().position(|entry| entry.uuid == uuid) {
+                view.set_selected_item(index);
+            }
+        });
+    }
 }
 
 fn fuzzy_match_on_edit(siv: &mut Cursive, content: &str) {
     let mut table = siv.find_name::<VaultTableView>("password_table").unwrap();
-    let items = &siv.user_data::<VaultData>().unwrap().decrypted;
+    let vault = siv.user_data::<VaultData>().unwrap();
+    let (folder, collection) = (vault.folder_filter, vault.collection_filter);
+    let items = vault
+        .decrypted
+        .iter()
+        .filter(|entry| folder.matches(entry) && collection.matches(entry));
 
     // If no search term is present, sort by name and favorite by default
     if content.is_empty() {
-        table.set_items(items.clone());
+        table.set_items(items.cloned().collect());
         table.sort_by(VaultColumn::Name, Ordering::Less);
         table.sort_by(VaultColumn::Favorite, Ordering::Less);
         return;
     }
 
     let matcher = SkimMatcherV2::default();
-    let mut items: Vec<(i64, VaultEntry)> = items.iter()
+    let mut items: Vec<(i64, VaultRow)> = items
         .map(|entry| {
             (matcher.fuzzy_match(&entry.name, content), entry.clone())
         })
@@ -232,69 +768,282 @@ fn fuzzy_match_on_edit(siv: &mut Cursive, content: &str) {
 
     items.sort_by(|a, b| a.0.cmp(&b.0).reverse());
 
-    let items = items.iter().map(|(_, entry)| entry.clone()).collect();
+    let items = items.into_iter().map(|(_, entry)| entry).collect();
     table.set_selected_row(0);
     table.set_items(items);
 }
 
-fn get_app_data_path() -> Result<PathBuf, String> {
-    let project_dirs = directories::ProjectDirs::from("", "", "bwtui")
-        .ok_or("could not retrieve data directory path")?;
+/// Uses the given directory for all stored data instead of the platform's data directory.
+pub fn set_data_dir(path: PathBuf) {
+    let _ = DATA_DIR.set(path);
+}
 
-    let target_dir = project_dirs.data_local_dir();
+/// Returns the directory all data is stored in, without creating it.
+fn data_dir() -> Result<PathBuf, Error> {
+    match DATA_DIR.get() {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(directories::ProjectDirs::from("", "", "bwtui")
+            .ok_or(Error::NoProjectDir("data"))?
+            .data_local_dir()
+            .to_owned()),
+    }
+}
 
-    fs::create_dir_all(target_dir).map_err(|_| "could not create data directory")?;
+fn account_dir(dir: &Path, account: &Account) -> PathBuf {
+    dir.join(ACCOUNTS_DIR).join(account.dir_name())
+}
 
-    let mut path = PathBuf::new();
-    path.push(target_dir);
+pub fn get_app_data_path() -> Result<PathBuf, Error> {
+    let target_dir = data_dir()?;
+
+    fs::create_dir_all(&target_dir).map_err(Error::storage(&target_dir))?;
+
+    Ok(target_dir)
+}
+
+fn get_account_path(account: &Account) -> Result<PathBuf, Error> {
+    let path = account_dir(&get_app_data_path()?, account);
+
+    fs::create_dir_all(&path).map_err(Error::storage(&path))?;
 
     Ok(path)
 }
 
-fn save_data_to<T>(filename: &str, data: &T) -> Result<(), String>
+fn save_data_to<T>(dir: &Path, filename: &str, data: &T) -> Result<(), Error>
 where
     T: Serialize,
 {
-    let mut path = get_app_data_path()
-        .map_err(|err| format!("failed to write sync data: {}", err))?;
+    let path = dir.join(filename);
+    let temp_path = dir.join(format!(".{}.tmp", filename));
 
-    path.push(filename);
+    write_file(&temp_path, data)
+        .and_then(|_| fs::rename(&temp_path, &path))
+        .map_err(|source| {
+            let _ = fs::remove_file(&temp_path);
+            Error::Storage { path, source }
+        })
+}
 
-    let file = File::create(path)
-        .map_err(|err| format!("failed to write sync data: {}", err))?;
+/// Writes the data to a new file only accessible by the user. Together with renaming it
+/// afterwards, a crash never leaves a partially written file behind.
+fn write_file<T>(path: &Path, data: &T) -> io::Result<()>
+where
+    T: Serialize,
+{
+    let _ = fs::remove_file(path);
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
 
-    let writer = BufWriter::new(file);
-    serde_json::to_writer(writer, data)
-        .map_err(|err| format!("failed to write sync data: {}", err))
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, data)?;
+
+    writer.into_inner().map_err(|err| err.into_error())?.sync_all()
 }
 
-fn read_data_from<T>(filename: &str) -> Result<T, String>
+fn read_data_from<T>(dir: &Path, filename: &str) -> Result<T, Error>
 where
     T: DeserializeOwned,
 {
-    let mut path = get_app_data_path()
-        .map_err(|err| format!("failed to read sync data: {}", err))?;
+    let path = dir.join(filename);
+    let file = File::open(&path).map_err(Error::storage(&path))?;
 
-    path.push(filename);
+    let reader = BufReader::new(file);
+    serde_json::from_reader(reader).map_err(|source| Error::Parse { path, source })
+}
 
-    let file = File::open(path)
-        .map_err(|err| format!("failed to read sync data: {}", err))?;
+/// Returns all accounts with stored data, sorted by email address.
+pub fn read_accounts() -> Vec<Account> {
+    let dir = match data_dir() {
+        Ok(dir) => dir,
+        Err(_) => return Vec::new(),
+    };
 
-    let reader = BufReader::new(file);
-    serde_json::from_reader(reader)
-        .map_err(|err| format!("failed to read sync data: {}", err))
+    // Failing to move the data of older versions only means having to sync again
+    let _ = migrate_legacy_data(&dir);
+
+    let mut accounts: Vec<Account> = fs::read_dir(dir.join(ACCOUNTS_DIR))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| read_data_from(&entry.path(), "account.json").ok())
+        .collect();
+
+    accounts.sort_by(|a, b| UniCase::new(&a.email).cmp(&UniCase::new(&b.email)));
+    accounts
 }
 
-pub fn read_local_data() -> Result<VaultData, String> {
-    let auth = read_data_from("auth.json")?;
-    let sync = read_data_from("vault.json")?;
+/// Moves the data of a single account, as stored by older versions, into its account directory.
+/// If the account already has data stored in there, the older data is dropped instead.
+///
+/// The data is written anew, only accessible by the user. Older versions stored the tokens in
+/// plaintext, which cannot be sealed without unlocking the vault, so they are dropped and the
+/// next unlock logs in again.
+fn migrate_legacy_data(dir: &Path) -> Result<(), Error> {
+    const LEGACY_FILES: [&str; 2] = ["auth.json", "vault.json"];
+
+    if !dir.join("vault.json").exists() {
+        return Ok(());
+    }
+
+    let auth: AuthData = read_data_from(dir, "auth.json")?;
+    let sync: SyncResponse = read_data_from(dir, "vault.json")?;
+    let vault = VaultData::new(auth, sync);
+    let account = Account::of(&vault);
+    let account_dir = account_dir(dir, &account);
 
-    Ok(VaultData { auth, sync, decrypted: Vec::new() })
+    if !account_dir.join("vault.json").exists() {
+        fs::create_dir_all(&account_dir).map_err(Error::storage(&account_dir))?;
+
+        save_data_to(&account_dir, "auth.json", &vault.auth)?;
+        save_data_to(&account_dir, "vault.json", &vault.sync)?;
+        save_data_to(&account_dir, "account.json", &account)?;
+    }
+
+    for filename in LEGACY_FILES {
+        let path = dir.join(filename);
+        fs::remove_file(&path).map_err(Error::storage(&path))?;
+    }
+
+    Ok(())
 }
 
-pub fn save_local_data(data: &VaultData) -> Result<(), String> {
-    save_data_to("auth.json", &data.auth)?;
-    save_data_to("vault.json", &data.sync)?;
+pub fn read_local_data(account: &Account) -> Result<VaultData, Error> {
+    let dir = account_dir(&data_dir()?, account);
+    let auth = read_data_from(&dir, "auth.json")?;
+    let sync = read_data_from(&dir, "vault.json")?;
+
+    Ok(VaultData::new(auth, sync))
+}
+
+/// Stores the auth and sync data. The vault must be unlocked, as the tokens are stored encrypted.
+pub fn save_local_data(data: &mut VaultData) -> Result<(), Error> {
+    data.auth.seal()?;
+
+    let account = Account::of(data);
+    let dir = get_account_path(&account)?;
+
+    save_data_to(&dir, "auth.json", &data.auth)?;
+    save_data_to(&dir, "vault.json", &data.sync)?;
+    save_data_to(&dir, "account.json", &account)?;
 
     Ok(())
 }
+
+/// The device is shared by all accounts, as the official clients do.
+pub fn read_device() -> Result<Device, Error> {
+    read_data_from(&data_dir()?, "device.json")
+}
+
+pub fn save_device(device: &Device) -> Result<(), Error> {
+    save_data_to(&get_app_data_path()?, "device.json", device)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+
+    use super::*;
+
+    const LEGACY_AUTH: &str = r#"{"access_token":"token","expires_in":3600,"token_type":"Bearer",
+        "refresh_token":"refresh","kdf":0,"kdf_iterations":1000}"#;
+    const LEGACY_SYNC: &str = include_str!("../bitwarden/tests/fixtures/sync_current.json");
+
+    fn account(server: ServerConfig, email: &str) -> Account {
+        Account { server, email: email.to_owned() }
+    }
+
+    fn legacy_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bwtui-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("auth.json"), LEGACY_AUTH).unwrap();
+        fs::write(dir.join("vault.json"), LEGACY_SYNC).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dir_name_identifies_account() {
+        let base = ServerConfig::from_base_url("https://vault.example.com");
+        let name = account(base.clone(), "User@Example.com").dir_name();
+
+        assert!(name.starts_with("user@example.com_vault.example.com_"), "{}", name);
+        assert_eq!(name, account(base.clone(), "user@example.com").dir_name());
+
+        // Only the API URL differs, so the readable part is the same
+        let other = ServerConfig {
+            api_url: "https://api.example.com".to_owned(),
+            ..base.clone()
+        };
+        assert_ne!(name, account(other, "user@example.com").dir_name());
+        assert_ne!(name, account(base, "other@example.com").dir_name());
+        assert!(account(ServerConfig::default(), "a/b@example.com")
+            .dir_name()
+            .starts_with("a_b@example.com_bitwarden.com_"));
+    }
+
+    #[test]
+    fn matches_server_and_email() {
+        let server = ServerConfig::from_base_url("https://vault.example.com");
+        let account = account(server.clone(), "user@example.com");
+
+        assert!(account.matches(None, None));
+        assert!(account.matches(Some(&server), None));
+        assert!(account.matches(None, Some("USER@example.com")));
+        assert!(account.matches(Some(&server), Some("user@example.com")));
+        assert!(!account.matches(Some(&ServerConfig::default()), None));
+        assert!(!account.matches(Some(&server), Some("other@example.com")));
+    }
+
+    #[test]
+    fn migrates_legacy_data() {
+        let dir = legacy_dir("migrate");
+        migrate_legacy_data(&dir).unwrap();
+
+        let account = account(ServerConfig::default(), "user@example.com");
+        let account_dir = account_dir(&dir, &account);
+
+        assert!(!dir.join("auth.json").exists());
+        assert!(!dir.join("vault.json").exists());
+
+        let sync: SyncResponse = read_data_from(&account_dir, "vault.json").unwrap();
+        assert_eq!(sync.profile.email, "user@example.com");
+
+        let stored: Account = read_data_from(&account_dir, "account.json").unwrap();
+        assert_eq!(stored, account);
+
+        // Rewritten only accessible by the user, without the plaintext tokens
+        for filename in ["auth.json", "vault.json", "account.json"] {
+            let mode = fs::metadata(account_dir.join(filename)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", filename);
+        }
+
+        let auth = fs::read_to_string(account_dir.join("auth.json")).unwrap();
+        assert!(!auth.contains("access_token") && !auth.contains("refresh_token"), "{}", auth);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn migration_keeps_existing_account_data() {
+        let dir = legacy_dir("migrate-existing");
+
+        let account = account(ServerConfig::default(), "user@example.com");
+        let account_dir = account_dir(&dir, &account);
+        fs::create_dir_all(&account_dir).unwrap();
+        fs::write(account_dir.join("vault.json"), "current").unwrap();
+
+        migrate_legacy_data(&dir).unwrap();
+
+        assert!(!dir.join("auth.json").exists());
+        assert!(!dir.join("vault.json").exists());
+        assert_eq!(fs::read_to_string(account_dir.join("vault.json")).unwrap(), "current");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: MIT
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+const ACCOUNT: &str = "4b1c6b5e-7b3a-4f4e-9b0a-2f6d1c9e8a70";
+const OTHER_ACCOUNT: &str = "0d3f7a2c-5e9b-4c1d-8a6f-3b2e1d0c9f84";
+
+/// 64 bytes of key material, base64-encoded.
+const KEY: &str =
+    "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0+Pw==";
+
+/// Agent running in its own data directory, killed when dropped.
+struct Agent {
+    child: Child,
+    dir: PathBuf,
+}
+
+impl Agent {
+    fn start(name: &str, timeout: u64) -> Self {
+        let dir = std::env::temp_dir().join(format!("bwtui-agent-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let config = dir.join("config.toml");
+        fs::write(&config, format!("agent_timeout = {}\n", timeout)).unwrap();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_bwtui"))
+            .arg("--config")
+            .arg(&config)
+            .arg("--data-dir")
+            .arg(&dir)
+            .arg("agent")
+            .spawn()
+            .unwrap();
+
+        let agent = Self { child, dir };
+
+        for _ in 0..100 {
+            if UnixStream::connect(agent.socket()).is_ok() {
+                return agent;
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        panic!("agent did not start");
+    }
+
+    fn socket(&self) -> PathBuf {
+        self.dir.join("agent.sock")
+    }
+
+    fn request(&self, request: &str) -> Value {
+        let mut stream = UnixStream::connect(self.socket()).unwrap();
+        writeln!(stream, "{}", request).unwrap();
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+
+        serde_json::from_str(&line).unwrap()
+    }
+
+    fn store(&self, account: &str) -> Value {
+        self.request(&json!({ "command": "store", "account": account, "key": KEY }).to_string())
+    }
+
+    fn get(&self, account: &str) -> Value {
+        self.request(&json!({ "command": "get", "account": account }).to_string())
+    }
+}
+
+impl Drop for Agent {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn stores_key_per_account_until_locked() {
+    let agent = Agent::start("store", 0);
+
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "locked" }));
+    assert_eq!(agent.store(ACCOUNT), json!({ "status": "ok" }));
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "ok", "key": KEY }));
+    assert_eq!(agent.get(OTHER_ACCOUNT), json!({ "status": "locked" }));
+
+    assert_eq!(agent.store(OTHER_ACCOUNT), json!({ "status": "ok" }));
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "ok", "key": KEY }));
+    assert_eq!(agent.get(OTHER_ACCOUNT), json!({ "status": "ok", "key": KEY }));
+
+    assert_eq!(agent.request(r#"{"command":"lock"}"#), json!({ "status": "ok" }));
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "locked" }));
+    assert_eq!(agent.get(OTHER_ACCOUNT), json!({ "status": "locked" }));
+}
+
+#[test]
+fn locks_single_account() {
+    let agent = Agent::start("lock-account", 0);
+
+    agent.store(ACCOUNT);
+    agent.store(OTHER_ACCOUNT);
+
+    let request = json!({ "command": "lock", "account": ACCOUNT }).to_string();
+    assert_eq!(agent.request(&request), json!({ "status": "ok" }));
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "locked" }));
+    assert_eq!(agent.get(OTHER_ACCOUNT), json!({ "status": "ok", "key": KEY }));
+}
+
+#[test]
+fn key_expires_after_timeout() {
+    let agent = Agent::start("expiry", 1);
+
+    agent.store(ACCOUNT);
+    assert_eq!(agent.get(ACCOUNT)["status"], "ok");
+
+    thread::sleep(Duration::from_millis(2500));
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "locked" }));
+}
+
+#[test]
+fn invalid_requests_are_rejected() {
+    let agent = Agent::start("invalid", 0);
+
+    assert_eq!(agent.request("{}")["status"], "error");
+    assert_eq!(agent.request(r#"{"command":"unlock"}"#)["status"], "error");
+
+    let short_key = json!({ "command": "store", "account": ACCOUNT, "key": "AAEC" });
+    assert_eq!(agent.request(&short_key.to_string())["status"], "error");
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "locked" }));
+}
+
+#[test]
+fn socket_is_only_accessible_by_owner() {
+    let agent = Agent::start("permissions", 0);
+    let mode = fs::metadata(agent.socket()).unwrap().permissions().mode();
+
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn idle_client_does_not_block_others() {
+    let agent = Agent::start("concurrent", 0);
+
+    // Connected, but sends only part of a request
+    let mut idle = UnixStream::connect(agent.socket()).unwrap();
+    write!(idle, r#"{{"command":"#).unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let start = Instant::now();
+    assert_eq!(agent.store(ACCOUNT), json!({ "status": "ok" }));
+    assert_eq!(agent.get(ACCOUNT), json!({ "status": "ok", "key": KEY }));
+    assert!(start.elapsed() < Duration::from_secs(2));
+}